@@ -2,6 +2,8 @@ use std::path::PathBuf;
 
 use clap::{Args, Parser, Subcommand};
 
+use crate::detect::DEFAULT_DEPTH;
+
 #[derive(Parser, Debug)]
 #[command(name = "devenv", version, about = "Simple dev environment manager", long_about = None)]
 pub struct Cli {
@@ -15,7 +17,18 @@ pub struct Cli {
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     /// Initialize a dev environment in the given project directory
-    Init { path: Option<PathBuf> },
+    Init {
+        path: Option<PathBuf>,
+        /// How many directory levels below the project to search for ecosystems
+        #[arg(long, default_value_t = DEFAULT_DEPTH)]
+        depth: usize,
+        /// Translate .devcontainer/devcontainer.json instead of detecting the project
+        #[arg(long)]
+        from_devcontainer: bool,
+        /// Import services from docker-compose.yml / compose.yaml without asking
+        #[arg(long)]
+        compose: bool,
+    },
     /// List running dev environments
     List,
     /// Start the named environment
@@ -26,10 +39,101 @@ pub enum Commands {
     Remove { name: Option<String> },
     /// Attach an interactive shell to the environment (or infer from CWD)
     Attach { name: Option<String> },
+    /// Show the host addresses of the environment's published ports (or infer from CWD)
+    Ports { name: Option<String> },
+    /// Show CPU, memory and process usage against the environment's limits (or infer from CWD)
+    Stats { name: Option<String> },
     /// Restart the environment: stop if running, then start (accepts same flags as start)
     Restart(StartArgs),
     /// Build the environment image without starting a container
     Build(BuildArgs),
+    /// List images built for the environment, newest first (or infer from CWD)
+    Images { name: Option<String> },
+    /// Show which image and packages detection picks for a project
+    Detect(DetectArgs),
+    /// Export the environment for other tools
+    Export {
+        #[command(subcommand)]
+        command: ExportCommand,
+    },
+    /// Inspect the generated Dockerfile
+    Dockerfile {
+        #[command(subcommand)]
+        command: DockerfileCommand,
+    },
+    /// Check devenv.toml
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct DetectArgs {
+    /// Project directory (defaults to CWD)
+    pub path: Option<PathBuf>,
+    /// How many directory levels below the project to search for ecosystems
+    #[arg(long, default_value_t = DEFAULT_DEPTH)]
+    pub depth: usize,
+    /// Print every rule evaluated and the files it matched
+    #[arg(long)]
+    pub explain: bool,
+    /// Print the full detection result as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ExportCommand {
+    /// Write .devcontainer/devcontainer.json from devenv.toml
+    Devcontainer(ExportArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// Environment name (optional; inferred from devenv.toml in CWD when omitted)
+    pub name: Option<String>,
+    /// Overwrite a devcontainer.json that was not generated by devenv
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DockerfileCommand {
+    /// Show how the Dockerfile on disk differs from the one devenv.toml generates
+    Diff(DockerfileDiffArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct DockerfileDiffArgs {
+    /// Environment name (optional; inferred from devenv.toml in CWD when omitted)
+    pub name: Option<String>,
+    /// Exit with an error if the Dockerfile is out of sync (for CI)
+    #[arg(long)]
+    pub check: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Report unknown keys and invalid values in devenv.toml
+    Validate(ConfigArgs),
+    /// Print devenv.toml, or with --resolved the config merged with its bases
+    Show(ShowArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigArgs {
+    /// Environment name (optional; inferred from devenv.toml in CWD when omitted)
+    pub name: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ShowArgs {
+    /// Environment name (optional; inferred from devenv.toml in CWD when omitted)
+    pub name: Option<String>,
+    /// Print the merged config, commenting each value with the file it came from
+    #[arg(long)]
+    pub resolved: bool,
 }
 
 #[derive(Args, Debug)]
@@ -105,4 +209,118 @@ mod tests {
             _ => panic!("expected build"),
         }
     }
+
+    #[test]
+    fn parses_images_with_optional_name() {
+        let cli = Cli::parse_from(["devenv", "images"]);
+        assert!(matches!(cli.command, Commands::Images { name: None }));
+        let cli = Cli::parse_from(["devenv", "images", "proj"]);
+        match cli.command {
+            Commands::Images { name } => assert_eq!(name.as_deref(), Some("proj")),
+            _ => panic!("expected images"),
+        }
+    }
+
+    #[test]
+    fn parses_dockerfile_diff_check() {
+        let cli = Cli::parse_from(["devenv", "dockerfile", "diff", "--check", "proj"]);
+        match cli.command {
+            Commands::Dockerfile {
+                command: DockerfileCommand::Diff(args),
+            } => {
+                assert!(args.check);
+                assert_eq!(args.name.as_deref(), Some("proj"));
+            }
+            _ => panic!("expected dockerfile diff"),
+        }
+    }
+
+    #[test]
+    fn parses_config_validate() {
+        let cli = Cli::parse_from(["devenv", "config", "validate", "proj"]);
+        match cli.command {
+            Commands::Config {
+                command: ConfigCommand::Validate(args),
+            } => assert_eq!(args.name.as_deref(), Some("proj")),
+            _ => panic!("expected config validate"),
+        }
+    }
+
+    #[test]
+    fn parses_config_show() {
+        let cli = Cli::parse_from(["devenv", "config", "show", "--resolved"]);
+        match cli.command {
+            Commands::Config {
+                command: ConfigCommand::Show(args),
+            } => {
+                assert!(args.name.is_none());
+                assert!(args.resolved);
+            }
+            _ => panic!("expected config show"),
+        }
+    }
+
+    #[test]
+    fn parses_init_depth() {
+        let cli = Cli::parse_from(["devenv", "init"]);
+        assert!(matches!(
+            cli.command,
+            Commands::Init {
+                path: None,
+                depth: DEFAULT_DEPTH,
+                from_devcontainer: false,
+                compose: false,
+            }
+        ));
+        let cli = Cli::parse_from([
+            "devenv",
+            "init",
+            "--depth",
+            "4",
+            "--from-devcontainer",
+            "--compose",
+            "proj",
+        ]);
+        match cli.command {
+            Commands::Init {
+                path,
+                depth,
+                from_devcontainer,
+                compose,
+            } => {
+                assert_eq!(path, Some(PathBuf::from("proj")));
+                assert_eq!(depth, 4);
+                assert!(from_devcontainer && compose);
+            }
+            _ => panic!("expected init"),
+        }
+    }
+
+    #[test]
+    fn parses_detect_flags() {
+        let cli = Cli::parse_from(["devenv", "detect", "--explain", "--json", "--depth", "1"]);
+        match cli.command {
+            Commands::Detect(args) => {
+                assert!(args.explain);
+                assert!(args.json);
+                assert_eq!(args.depth, 1);
+                assert!(args.path.is_none());
+            }
+            _ => panic!("expected detect"),
+        }
+    }
+
+    #[test]
+    fn parses_export_devcontainer() {
+        let cli = Cli::parse_from(["devenv", "export", "devcontainer", "--force"]);
+        match cli.command {
+            Commands::Export {
+                command: ExportCommand::Devcontainer(args),
+            } => {
+                assert!(args.force);
+                assert!(args.name.is_none());
+            }
+            _ => panic!("expected export devcontainer"),
+        }
+    }
 }
@@ -0,0 +1,491 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Value};
+
+use crate::{
+    config::{Healthcheck, Service},
+    docker::service::{parse_duration, parse_port},
+    util::{as_string, scalar_string, shell_quote},
+};
+
+/// Compose file names, in the order `docker compose` looks for them
+const CANDIDATES: &[&str] = &[
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yaml",
+    "docker-compose.yml",
+];
+
+/// Services translated from a compose file
+#[derive(Debug, Default)]
+pub struct Import {
+    /// Services in dependency order
+    pub services: Vec<Service>,
+    /// Compose services left out because the dev container replaces them
+    pub replaced: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Find the project's compose file, if any
+pub fn find(project_dir: &Path) -> Option<PathBuf> {
+    CANDIDATES
+        .iter()
+        .map(|c| project_dir.join(c))
+        .find(|p| p.is_file())
+}
+
+/// Translate the services of the compose file at `path` into devenv services.
+/// Services built from the project are left out, since the dev container
+/// takes their place.
+pub fn import(path: &Path) -> Result<Import> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+    let doc = parse(&contents).with_context(|| format!("Parsing {}", path.display()))?;
+    let Value::Object(mut doc) = doc else {
+        bail!("{} must contain a mapping", path.display());
+    };
+    let Some(Value::Object(services)) = doc.remove("services") else {
+        bail!("{} has no `services`", path.display());
+    };
+
+    let mut import = Import::default();
+    let warnings = &mut import.warnings;
+    if let Some(Value::Object(volumes)) = doc.remove("volumes") {
+        for (name, volume) in volumes {
+            if volume
+                .get("external")
+                .is_some_and(|e| e == &Value::Bool(true))
+            {
+                warnings.push(format!(
+                    "volumes.{name}: external volumes are not supported; a volume scoped to the environment is used instead"
+                ));
+            }
+        }
+    }
+    for key in ["version", "name"] {
+        doc.remove(key);
+    }
+    for key in doc.keys().filter(|k| !is_extension(k)) {
+        warnings.push(format!("{key}: not supported by devenv; ignored"));
+    }
+
+    // The service built from the project, or failing that the one mounting it
+    let builds: Vec<&String> = services
+        .iter()
+        .filter(|(_, svc)| svc.get("build").is_some())
+        .map(|(name, _)| name)
+        .collect();
+    import.replaced = if builds.is_empty() {
+        services
+            .iter()
+            .filter(|(_, svc)| mounts_project(svc))
+            .map(|(name, _)| name.clone())
+            .collect()
+    } else {
+        builds.into_iter().cloned().collect()
+    };
+
+    let mut imported = Vec::new();
+    for (name, svc) in &services {
+        if import.replaced.contains(name) {
+            continue;
+        }
+        let Value::Object(svc) = svc else {
+            bail!("services.{name} must be a mapping");
+        };
+        if let Some(service) = import_service(name, svc.clone(), &import.replaced, warnings)? {
+            imported.push(service);
+        }
+    }
+    let names: Vec<String> = imported.iter().map(|s| s.name.clone()).collect();
+    for svc in &mut imported {
+        svc.depends_on.retain(|dep| {
+            let known = names.contains(dep);
+            if !known {
+                warnings.push(format!(
+                    "services.{}.depends_on: '{dep}' was not imported; dependency dropped",
+                    svc.name
+                ));
+            }
+            known
+        });
+    }
+    import.services = dependency_order(imported)?;
+    Ok(import)
+}
+
+// Read YAML into JSON values, resolving anchors and `<<` merge keys
+fn parse(contents: &str) -> Result<Value> {
+    let mut doc: serde_yaml_ng::Value = serde_yaml_ng::from_str(contents)?;
+    doc.apply_merge()?;
+    Ok(serde_yaml_ng::from_value(doc)?)
+}
+
+fn import_service(
+    name: &str,
+    mut svc: Map<String, Value>,
+    replaced: &[String],
+    warnings: &mut Vec<String>,
+) -> Result<Option<Service>> {
+    let key = |k: &str| format!("services.{name}.{k}");
+    let Some(image) = svc.remove("image") else {
+        warnings.push(format!(
+            "services.{name}: has no `image`; service not imported"
+        ));
+        return Ok(None);
+    };
+    let mut service = Service {
+        name: name.to_string(),
+        image: as_string(&key("image"), &image)?,
+        env: BTreeMap::new(),
+        ports: Vec::new(),
+        volumes: Vec::new(),
+        depends_on: Vec::new(),
+        healthcheck: None,
+    };
+
+    if let Some(env) = svc.remove("environment") {
+        service.env = environment(&key("environment"), env)?;
+    }
+    for port in list(&key("ports"), svc.remove("ports"))? {
+        match port_spec(&port) {
+            Some(spec) if parse_port(&spec).is_ok() => service.ports.push(spec),
+            _ => warnings.push(format!(
+                "{}: '{}' is not supported; port not published",
+                key("ports"),
+                scalar_string(&port)
+            )),
+        }
+    }
+    for volume in list(&key("volumes"), svc.remove("volumes"))? {
+        match volume_spec(&volume) {
+            Some(spec) => service.volumes.push(spec),
+            None => warnings.push(format!(
+                "{}: '{}' is not supported; only SOURCE:TARGET volumes and binds are imported",
+                key("volumes"),
+                scalar_string(&volume)
+            )),
+        }
+    }
+    match svc.remove("depends_on") {
+        Some(Value::Object(deps)) => service.depends_on = deps.keys().cloned().collect(),
+        deps => {
+            for dep in list(&key("depends_on"), deps)? {
+                service
+                    .depends_on
+                    .push(as_string(&key("depends_on"), &dep)?);
+            }
+        }
+    }
+    // The dev container starts after every service, so it needs no dependency
+    service.depends_on.retain(|dep| !replaced.contains(dep));
+    if let Some(hc) = svc.remove("healthcheck") {
+        service.healthcheck = healthcheck(&key("healthcheck"), hc, warnings)?;
+    }
+
+    for k in svc.keys().filter(|k| !is_extension(k)) {
+        warnings.push(format!("{}: not supported by devenv; ignored", key(k)));
+    }
+    Ok(Some(service))
+}
+
+// Mapping or `KEY=VALUE` list; a bare `KEY` takes its value from the host
+fn environment(key: &str, env: Value) -> Result<BTreeMap<String, String>> {
+    let from_host = |k: &str| format!("${{{k}}}");
+    Ok(match env {
+        Value::Object(map) => map
+            .into_iter()
+            .map(|(k, v)| {
+                let v = match v {
+                    Value::Null => from_host(&k),
+                    v => scalar_string(&v),
+                };
+                (k, v)
+            })
+            .collect(),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| {
+                let item = as_string(key, item)?;
+                Ok(match item.split_once('=') {
+                    Some((k, v)) => (k.to_string(), v.to_string()),
+                    None => (item.clone(), from_host(&item)),
+                })
+            })
+            .collect::<Result<_>>()?,
+        _ => bail!("{key} must be a mapping or list"),
+    })
+}
+
+// Short syntax as is; long syntax rebuilt as `[IP:][PUBLISHED:]TARGET[/PROTOCOL]`
+fn port_spec(port: &Value) -> Option<String> {
+    match port {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Object(long) => {
+            let field = |k: &str| long.get(k).map(scalar_string);
+            let mut spec = field("target")?;
+            if let Some(published) = field("published") {
+                spec = format!("{published}:{spec}");
+                if let Some(ip) = field("host_ip") {
+                    spec = format!("{ip}:{spec}");
+                }
+            }
+            if let Some(protocol) = field("protocol") {
+                spec = format!("{spec}/{protocol}");
+            }
+            Some(spec)
+        }
+        _ => None,
+    }
+}
+
+// `SOURCE:TARGET[:MODE]`, or the long syntax for volumes and binds
+fn volume_spec(volume: &Value) -> Option<String> {
+    match volume {
+        Value::String(s) if s.contains(':') => Some(s.clone()),
+        Value::Object(long) => {
+            let field = |k: &str| long.get(k).map(scalar_string);
+            if !matches!(field("type").as_deref(), Some("volume" | "bind")) {
+                return None;
+            }
+            let mut spec = format!("{}:{}", field("source")?, field("target")?);
+            if long.get("read_only") == Some(&Value::Bool(true)) {
+                spec.push_str(":ro");
+            }
+            Some(spec)
+        }
+        _ => None,
+    }
+}
+
+fn healthcheck(key: &str, hc: Value, warnings: &mut Vec<String>) -> Result<Option<Healthcheck>> {
+    let Value::Object(mut hc) = hc else {
+        bail!("{key} must be a mapping");
+    };
+    if hc.remove("disable") == Some(Value::Bool(true)) {
+        return Ok(None);
+    }
+    // `CMD` runs its arguments directly; devenv healthchecks always run in a shell
+    let test = match hc.remove("test") {
+        Some(Value::String(cmd)) => cmd,
+        Some(Value::Array(args)) => {
+            let args: Vec<String> = args.iter().map(scalar_string).collect();
+            match args.split_first() {
+                Some((kind, rest)) if kind == "CMD" => rest
+                    .iter()
+                    .map(|a| shell_quote(a))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                Some((kind, [cmd])) if kind == "CMD-SHELL" => cmd.clone(),
+                Some((kind, _)) if kind == "NONE" => return Ok(None),
+                _ => bail!("{key}.test must start with CMD, CMD-SHELL or NONE"),
+            }
+        }
+        None => {
+            warnings.push(format!("{key}: has no `test`; healthcheck not imported"));
+            return Ok(None);
+        }
+        Some(_) => bail!("{key}.test must be a string or list"),
+    };
+
+    let mut duration = |field: &str| {
+        let value = hc.remove(field).map(|v| scalar_string(&v))?;
+        match parse_duration(&value) {
+            Ok(_) => Some(value),
+            Err(e) => {
+                warnings.push(format!("{key}.{field}: {e}; ignored"));
+                None
+            }
+        }
+    };
+    let interval = duration("interval");
+    let timeout = duration("timeout");
+    let start_period = duration("start_period");
+    let retries = match hc.remove("retries") {
+        Some(v) => Some(
+            scalar_string(&v)
+                .parse()
+                .with_context(|| format!("{key}.retries must be a number"))?,
+        ),
+        None => None,
+    };
+    for k in hc.keys() {
+        warnings.push(format!("{key}.{k}: not supported by devenv; ignored"));
+    }
+    Ok(Some(Healthcheck {
+        test,
+        interval,
+        timeout,
+        start_period,
+        retries,
+    }))
+}
+
+// Order services so that each comes after the services it depends on
+fn dependency_order(mut pending: Vec<Service>) -> Result<Vec<Service>> {
+    let mut ordered: Vec<Service> = Vec::new();
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|svc| {
+            svc.depends_on
+                .iter()
+                .all(|dep| ordered.iter().any(|s| &s.name == dep))
+        });
+        let Some(ready) = ready else {
+            let names: Vec<_> = pending.iter().map(|s| s.name.as_str()).collect();
+            bail!("services {} depend on each other", names.join(", "));
+        };
+        ordered.push(pending.remove(ready));
+    }
+    Ok(ordered)
+}
+
+// Whether the service bind-mounts the project directory
+fn mounts_project(svc: &Value) -> bool {
+    let Some(Value::Array(volumes)) = svc.get("volumes") else {
+        return false;
+    };
+    volumes.iter().any(|v| {
+        let source = match v {
+            Value::String(s) => s.split(':').next().unwrap_or_default().to_string(),
+            Value::Object(long) => long.get("source").map(scalar_string).unwrap_or_default(),
+            _ => return false,
+        };
+        matches!(source.trim_end_matches('/'), "." | "")
+    })
+}
+
+// `x-` extension fields hold anchors and are ignored by Compose itself
+fn is_extension(key: &str) -> bool {
+    key.starts_with("x-")
+}
+
+fn list(key: &str, value: Option<Value>) -> Result<Vec<Value>> {
+    match value {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Array(items)) => Ok(items),
+        Some(_) => bail!("{key} must be a list"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn import_str(compose: &str) -> Import {
+        let td = TempDir::new().unwrap();
+        fs::write(td.path().join("compose.yaml"), compose).unwrap();
+        import(&find(td.path()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn imports_services_without_the_dev_service() {
+        let import = import_str(
+            r#"
+services:
+  app:
+    build: .
+    depends_on: [db]
+  worker:
+    image: example/worker
+    environment:
+      - QUEUE=default
+      - API_TOKEN
+    depends_on:
+      db:
+        condition: service_healthy
+      cache:
+        condition: service_started
+  db:
+    image: postgres:16
+    environment:
+      POSTGRES_PASSWORD: dev
+    ports:
+      - "5432:5432"
+      - target: 8080
+        published: 18080
+        host_ip: 127.0.0.1
+    volumes:
+      - pgdata:/var/lib/postgresql/data
+      - ./init.sql:/docker-entrypoint-initdb.d/init.sql:ro
+    healthcheck:
+      test: ["CMD", "pg_isready", "-U", "postgres"]
+      interval: 5s
+      retries: 5
+  cache:
+    image: redis:7
+    command: redis-server --appendonly yes
+volumes:
+  pgdata:
+"#,
+        );
+        assert_eq!(import.replaced, vec!["app"]);
+        let names: Vec<_> = import.services.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["cache", "db", "worker"]);
+
+        let db = &import.services[1];
+        assert_eq!(db.env["POSTGRES_PASSWORD"], "dev");
+        assert_eq!(db.ports, vec!["5432:5432", "127.0.0.1:18080:8080"]);
+        assert_eq!(
+            db.volumes,
+            vec![
+                "pgdata:/var/lib/postgresql/data",
+                "./init.sql:/docker-entrypoint-initdb.d/init.sql:ro"
+            ]
+        );
+        let hc = db.healthcheck.as_ref().unwrap();
+        assert_eq!(hc.test, "pg_isready -U postgres");
+        assert_eq!(hc.interval.as_deref(), Some("5s"));
+        assert_eq!(hc.retries, Some(5));
+
+        let worker = &import.services[2];
+        assert_eq!(worker.env["API_TOKEN"], "${API_TOKEN}");
+        assert_eq!(worker.depends_on, vec!["cache", "db"]);
+
+        assert_eq!(
+            import.warnings,
+            vec!["services.cache.command: not supported by devenv; ignored"]
+        );
+    }
+
+    #[test]
+    fn replaces_the_service_mounting_the_project() {
+        let import = import_str(
+            "services:\n  dev:\n    image: node:20\n    volumes: ['.:/app']\n  db:\n    image: postgres\n    ports: ['8000-8010:8000-8010']\n    volumes: ['/data']\n",
+        );
+        assert_eq!(import.replaced, vec!["dev"]);
+        assert_eq!(import.services.len(), 1);
+        assert!(import.services[0].ports.is_empty());
+        assert_eq!(import.warnings.len(), 2, "{:?}", import.warnings);
+    }
+
+    #[test]
+    fn resolves_anchors_and_unquoted_scalars() {
+        let import = import_str(
+            "x-env: &env\n  DEBUG: true\n  PORT: 5432\nservices:\n  db:\n    image: postgres\n    x-owner: data\n    environment:\n      <<: *env\n      PORT: 5433\n    ports:\n      - 5433\n",
+        );
+        let db = &import.services[0];
+        assert_eq!(db.env["DEBUG"], "true");
+        assert_eq!(db.env["PORT"], "5433");
+        assert_eq!(db.ports, vec!["5433"]);
+        assert!(import.warnings.is_empty(), "{:?}", import.warnings);
+    }
+
+    #[test]
+    fn rejects_dependency_cycles() {
+        let td = TempDir::new().unwrap();
+        let path = td.path().join("docker-compose.yml");
+        fs::write(
+            &path,
+            "services:\n  a:\n    image: x\n    depends_on: [b]\n  b:\n    image: y\n    depends_on: [a]\n",
+        )
+        .unwrap();
+        assert!(import(&path).is_err());
+    }
+}
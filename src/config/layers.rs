@@ -0,0 +1,747 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fs,
+    io::ErrorKind,
+    ops::Range,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use toml::{
+    Spanned, Table, Value,
+    de::{DeTable, DeValue},
+};
+use tracing::warn;
+
+use super::{LOCAL_FILENAME, validate::Invalid};
+use crate::interpolate::expand_path;
+
+/// The config a devenv.toml builds on: a path (relative to the file, `~` and
+/// `${VAR}` expanded) or a file in a git repository
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Extends {
+    Path(String),
+    Git(GitBase),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GitBase {
+    /// Repository URL, as understood by `git clone`
+    pub git: String,
+    /// Branch, tag or commit; the remote's default branch when unset
+    pub rev: Option<String>,
+    /// Path of the config file inside the repository
+    pub path: String,
+}
+
+/// One file of a layered config
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub path: PathBuf,
+    /// How the file is shown in `config show --resolved`: relative to the
+    /// project, or the repository it was checked out from
+    pub label: String,
+    pub source: String,
+}
+
+/// Where a merged value came from: a file and the key path inside it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Origin {
+    pub file: usize,
+    pub path: Vec<String>,
+}
+
+/// devenv.toml merged with the configs it extends and devenv.local.toml
+#[derive(Debug, Clone, Default)]
+pub struct Layers {
+    /// Bases first, then the project's devenv.toml and its devenv.local.toml
+    pub files: Vec<Layer>,
+    /// Index of the project's devenv.toml in `files`
+    pub project: usize,
+    pub merged: Table,
+    /// Origins of scalars and array items by merged key path; numeric parts
+    /// index into arrays
+    origins: BTreeMap<Vec<String>, Origin>,
+}
+
+impl Layers {
+    // Merge `table`, the contents of `layer`, on top
+    fn push(&mut self, layer: Layer, table: Table) {
+        let file = self.files.len();
+        self.files.push(layer);
+        let origins = &mut self.origins;
+        merge(&mut self.merged, table, &mut |merged, local| {
+            origins.retain(|p, _| !p.starts_with(&merged));
+            origins.insert(merged, Origin { file, path: local });
+        });
+    }
+
+    /// Origin of the value at `path`, found through the closest value
+    /// containing it. For a table assembled from several files, the file of
+    /// its first key.
+    pub fn origin(&self, path: &[String]) -> Option<Origin> {
+        if let Some((len, origin)) = (0..=path.len())
+            .rev()
+            .find_map(|len| Some((len, self.origins.get(&path[..len])?)))
+        {
+            return Some(Origin {
+                file: origin.file,
+                path: [&origin.path[..], &path[len..]].concat(),
+            });
+        }
+        let (inner, origin) = self
+            .origins
+            .range(path.to_vec()..)
+            .next()
+            .filter(|(p, _)| p.starts_with(path))?;
+        let len = origin.path.len().checked_sub(inner.len() - path.len())?;
+        Some(Origin {
+            file: origin.file,
+            path: origin.path[..len].to_vec(),
+        })
+    }
+
+    /// Comment each line of `text`, the merged config serialized, with the
+    /// file its value came from
+    pub fn annotate(&self, text: &str) -> String {
+        let mut labels = BTreeMap::new();
+        if let Ok(doc) = DeTable::parse(text) {
+            let mut values = Vec::new();
+            collect_values(doc.get_ref(), &mut Vec::new(), &mut values);
+            for (path, span) in values {
+                // The last line, so comments stay out of multi-line strings
+                let line = text[..span.end].matches('\n').count();
+                let label = self
+                    .origin(&path)
+                    .map_or("(default)", |o| self.files[o.file].label.as_str());
+                labels.entry(line).or_insert(label);
+            }
+        }
+        let width = text
+            .lines()
+            .enumerate()
+            .filter(|(i, _)| labels.contains_key(i))
+            .map(|(_, line)| line.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(60);
+        let mut out = String::with_capacity(text.len() * 2);
+        for (i, line) in text.lines().enumerate() {
+            match labels.get(&i) {
+                Some(label) => out.push_str(&format!("{line:<width$}  # {label}\n")),
+                None => out.push_str(&format!("{line}\n")),
+            }
+        }
+        out
+    }
+}
+
+// Key paths and spans of scalars, array items and empty arrays and tables
+fn collect_values(
+    table: &DeTable,
+    path: &mut Vec<String>,
+    out: &mut Vec<(Vec<String>, Range<usize>)>,
+) {
+    for (key, value) in table {
+        path.push(key.get_ref().to_string());
+        collect_value(value, path, out);
+        path.pop();
+    }
+}
+
+fn collect_value(
+    value: &Spanned<DeValue>,
+    path: &mut Vec<String>,
+    out: &mut Vec<(Vec<String>, Range<usize>)>,
+) {
+    match value.get_ref() {
+        DeValue::Table(t) if !t.is_empty() => collect_values(t, path, out),
+        DeValue::Array(items) if !items.is_empty() => {
+            for (i, item) in items.iter().enumerate() {
+                path.push(i.to_string());
+                collect_value(item, path, out);
+                path.pop();
+            }
+        }
+        _ => out.push((path.clone(), value.span())),
+    }
+}
+
+/// Read the devenv.toml at `path`, every config it extends and the
+/// devenv.local.toml next to it
+pub fn load(path: &Path) -> Result<Layers> {
+    let source = fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+    from_source(path, source)
+}
+
+/// Like [`load`], with the contents of `path` already read
+pub fn from_source(path: &Path, source: String) -> Result<Layers> {
+    let label = path.file_name().map_or_else(
+        || path.display().to_string(),
+        |n| n.to_string_lossy().into(),
+    );
+    let mut chain = Vec::new();
+    let (mut layers, extends) = load_layer(path, label, "", source, &mut chain)?;
+    layers.project = layers.files.len() - 1;
+    // Only the project's own `extends` is kept; bases' are resolved already
+    if let Some(extends) = extends {
+        let key = vec!["extends".to_string()];
+        layers.merged.insert(key[0].clone(), extends);
+        let origin = Origin {
+            file: layers.project,
+            path: key.clone(),
+        };
+        layers.origins.insert(key, origin);
+    }
+
+    // Personal overrides, kept out of version control
+    let local = path.with_file_name(LOCAL_FILENAME);
+    let source = match fs::read_to_string(&local) {
+        Ok(source) => source,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(layers),
+        Err(e) => return Err(e).with_context(|| format!("Reading {}", local.display())),
+    };
+    let table: Table = toml::from_str(&source).map_err(|e| Invalid::new(&local, &source, &e))?;
+    if table.contains_key("extends") {
+        bail!("{LOCAL_FILENAME} cannot use `extends`; set it in devenv.toml");
+    }
+    let layer = Layer {
+        path: local,
+        label: LOCAL_FILENAME.into(),
+        source,
+    };
+    layers.push(layer, table);
+    Ok(layers)
+}
+
+// `dir` is the directory of `label`, used to label the file's bases
+fn load_layer(
+    path: &Path,
+    label: String,
+    dir: &str,
+    source: String,
+    chain: &mut Vec<PathBuf>,
+) -> Result<(Layers, Option<Value>)> {
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if chain.contains(&canonical) {
+        bail!("{label} extends itself");
+    }
+    chain.push(canonical);
+
+    let mut table: Table = toml::from_str(&source).map_err(|e| Invalid::new(path, &source, &e))?;
+    let extends = table.remove("extends");
+    let mut layers = match &extends {
+        None => Layers::default(),
+        Some(value) => {
+            let base = Extends::deserialize(value.clone()).map_err(|_| {
+                anyhow!("{label}: `extends` must be a path or a table with `git` and `path`")
+            })?;
+            let parent = path.parent().unwrap_or(Path::new("."));
+            let (base_path, base_label, base_dir) = resolve(&base, parent, dir)?;
+            let base_source = fs::read_to_string(&base_path).with_context(|| {
+                format!("Reading {}, which {label} extends", base_path.display())
+            })?;
+            load_layer(&base_path, base_label, &base_dir, base_source, chain)?.0
+        }
+    };
+    chain.pop();
+
+    let layer = Layer {
+        path: path.to_path_buf(),
+        label,
+        source,
+    };
+    layers.push(layer, table);
+    Ok((layers, extends))
+}
+
+// Path, label and label directory of a base config
+fn resolve(base: &Extends, parent: &Path, dir: &str) -> Result<(PathBuf, String, String)> {
+    match base {
+        Extends::Path(p) => {
+            let path = expand_path(p, parent)?;
+            let label = if Path::new(p).is_relative() && !p.starts_with(['~', '$']) {
+                join_label(dir, p)
+            } else {
+                path.display().to_string()
+            };
+            let dir = label.rsplit_once('/').map_or("", |(d, _)| d).to_string();
+            Ok((path, label, dir))
+        }
+        Extends::Git(git) => {
+            if !Path::new(&git.path).is_relative() {
+                bail!("`extends.path` must be relative to the repository root");
+            }
+            let checkout = checkout(git, &extends_cache_dir())?;
+            let repo = match &git.rev {
+                Some(rev) => format!("{}@{rev}", git.git),
+                None => git.git.clone(),
+            };
+            let dir = git.path.rsplit_once('/').map_or("", |(d, _)| d);
+            Ok((
+                checkout.join(&git.path),
+                format!("{repo}:{}", git.path),
+                join_label(&format!("{repo}:"), dir),
+            ))
+        }
+    }
+}
+
+fn join_label(dir: &str, path: &str) -> String {
+    let path = path.trim_start_matches("./");
+    if dir.is_empty() || dir.ends_with(':') {
+        format!("{dir}{path}")
+    } else {
+        format!("{dir}/{path}")
+    }
+}
+
+/// Merge `layer` into `base`: tables merge key by key, arrays append and
+/// anything else replaces. `record` receives the merged and the layer's key
+/// path of every value taken from `layer`.
+fn merge(base: &mut Table, layer: Table, record: &mut impl FnMut(Vec<String>, Vec<String>)) {
+    merge_at(base, layer, &mut Vec::new(), &mut Vec::new(), record);
+}
+
+fn merge_at(
+    base: &mut Table,
+    layer: Table,
+    merged_path: &mut Vec<String>,
+    local_path: &mut Vec<String>,
+    record: &mut impl FnMut(Vec<String>, Vec<String>),
+) {
+    for (key, value) in layer {
+        merged_path.push(key.clone());
+        local_path.push(key.clone());
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(b)), Value::Table(t)) => {
+                merge_at(b, t, merged_path, local_path, record);
+            }
+            (Some(Value::Array(b)), Value::Array(items)) => {
+                let offset = b.len();
+                for (i, item) in items.into_iter().enumerate() {
+                    let at = |path: &[String], i: usize| [path, &[i.to_string()]].concat();
+                    record(at(merged_path, offset + i), at(local_path, i));
+                    b.push(item);
+                }
+            }
+            (_, value) => {
+                record_value(&value, merged_path, local_path, record);
+                base.insert(key, value);
+            }
+        }
+        merged_path.pop();
+        local_path.pop();
+    }
+}
+
+// Tables record their keys; scalars, arrays items and empty arrays themselves
+fn record_value(
+    value: &Value,
+    merged_path: &mut Vec<String>,
+    local_path: &mut Vec<String>,
+    record: &mut impl FnMut(Vec<String>, Vec<String>),
+) {
+    match value {
+        Value::Table(t) if !t.is_empty() => {
+            for (key, value) in t {
+                merged_path.push(key.clone());
+                local_path.push(key.clone());
+                record_value(value, merged_path, local_path, record);
+                merged_path.pop();
+                local_path.pop();
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            record(merged_path.clone(), local_path.clone());
+            for i in 0..items.len() {
+                let at = |path: &[String]| [path, &[i.to_string()]].concat();
+                record(at(merged_path), at(local_path));
+            }
+        }
+        _ => record(merged_path.clone(), local_path.clone()),
+    }
+}
+
+/// Merge `changes` into `table` without tracking origins
+pub fn merge_into(table: &mut Table, changes: Table) {
+    merge(table, changes, &mut |_, _| {});
+}
+
+/// What `current` adds to `loaded`: new keys, changed values and items
+/// appended to arrays
+pub fn diff(current: &Table, loaded: &Table) -> Table {
+    let mut changes = Table::new();
+    for (key, value) in current {
+        let change = match (value, loaded.get(key)) {
+            (value, Some(old)) if value == old => None,
+            (Value::Table(t), Some(Value::Table(old))) => Some(Value::Table(diff(t, old)))
+                .filter(|v| v.as_table().is_some_and(|t| !t.is_empty())),
+            (Value::Array(items), Some(Value::Array(old))) if items.starts_with(old) => {
+                Some(Value::Array(items[old.len()..].to_vec()))
+            }
+            (value, _) => Some(value.clone()),
+        };
+        if let Some(change) = change {
+            changes.insert(key.clone(), change);
+        }
+    }
+    changes
+}
+
+/// Directory holding git checkouts of extended configs
+fn extends_cache_dir() -> PathBuf {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(dirs::cache_dir)
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join("devenv").join("extends")
+}
+
+/// Check out `base.rev`, or the remote's default branch without one, in a
+/// directory under `cache` kept per repository and revision. Branches and tags
+/// are fetched on every load so the base follows them; a full commit id
+/// already fetched is used as is. If fetching fails, the last checkout is kept.
+fn checkout(base: &GitBase, cache: &Path) -> Result<PathBuf> {
+    let rev = base.rev.as_deref();
+    let key = format!("{}\n{}", base.git, rev.unwrap_or_default());
+    let name: String = Sha256::digest(key)
+        .iter()
+        .take(8)
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    let dir = cache.join(name);
+    let label = match rev {
+        Some(rev) => format!("{}@{rev}", base.git),
+        None => base.git.clone(),
+    };
+    let in_dir = |args: &[&str]| {
+        let mut full: Vec<&OsStr> = vec!["-C".as_ref(), dir.as_os_str()];
+        full.extend(args.iter().map(OsStr::new));
+        git(&full)
+    };
+
+    if !dir.join(".git").exists() {
+        fs::create_dir_all(&dir).with_context(|| format!("Creating {}", dir.display()))?;
+        in_dir(&["init", "--quiet"])?;
+        in_dir(&["remote", "add", "origin", &base.git])?;
+    }
+    if let Some(rev) = rev
+        && rev.len() == 40
+        && is_hex(rev)
+        && in_dir(&["checkout", "--quiet", "--detach", rev]).is_ok()
+    {
+        return Ok(dir);
+    }
+    let fetched = in_dir(&["fetch", "--quiet", "origin", rev.unwrap_or("HEAD")]);
+    match (fetched, rev.filter(|r| is_hex(r))) {
+        (Ok(()), _) => in_dir(&["checkout", "--quiet", "--detach", "FETCH_HEAD"])
+            .with_context(|| format!("Checking out {label}"))?,
+        // Abbreviated commit ids can't be fetched by name
+        (Err(_), Some(id)) => {
+            in_dir(&["fetch", "--quiet", "--tags", "origin"])
+                .with_context(|| format!("Fetching {label}"))?;
+            in_dir(&["checkout", "--quiet", "--detach", id])
+                .with_context(|| format!("Checking out {label}"))?;
+        }
+        (Err(e), None) if in_dir(&["rev-parse", "--quiet", "--verify", "HEAD"]).is_ok() => {
+            warn!("Warning: could not fetch {label}, using the last checkout: {e:#}");
+        }
+        (Err(e), None) => return Err(e.context(format!("Fetching {label}"))),
+    }
+    Ok(dir)
+}
+
+fn is_hex(rev: &str) -> bool {
+    rev.len() >= 4 && rev.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn git(args: &[&OsStr]) -> Result<()> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .context("Running git")?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn path(p: &str) -> Vec<String> {
+        p.split('.').map(String::from).collect()
+    }
+
+    #[test]
+    fn merges_bases_and_tracks_origins() {
+        let td = TempDir::new().unwrap();
+        let platform = td.path().join("platform");
+        let app = td.path().join("app");
+        fs::create_dir_all(&platform).unwrap();
+        fs::create_dir_all(&app).unwrap();
+        fs::write(
+            platform.join("common.toml"),
+            "[devenv]\npackages = [\"git\"]\nenv = { TZ = \"UTC\" }\n",
+        )
+        .unwrap();
+        fs::write(
+            platform.join("devenv.base.toml"),
+            r#"extends = "common.toml"
+
+[devenv]
+name = "base"
+image = "debian:bookworm-slim"
+packages = ["curl"]
+commands = ["make setup"]
+
+[devenv.zed_remote]
+enabled = true
+ssh_port = 2222
+"#,
+        )
+        .unwrap();
+        let project = app.join("devenv.toml");
+        fs::write(
+            &project,
+            r#"extends = "../platform/devenv.base.toml"
+
+[devenv]
+name = "app"
+packages = ["jq"]
+env = { LOG = "debug" }
+
+[devenv.zed_remote]
+ssh_port = 2223
+"#,
+        )
+        .unwrap();
+
+        let layers = load(&project).unwrap();
+        let labels: Vec<_> = layers.files.iter().map(|f| f.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            [
+                "../platform/common.toml",
+                "../platform/devenv.base.toml",
+                "devenv.toml"
+            ]
+        );
+        let expected: Table = toml::from_str(
+            r#"extends = "../platform/devenv.base.toml"
+
+[devenv]
+name = "app"
+image = "debian:bookworm-slim"
+packages = ["git", "curl", "jq"]
+commands = ["make setup"]
+env = { TZ = "UTC", LOG = "debug" }
+zed_remote = { enabled = true, ssh_port = 2223 }
+"#,
+        )
+        .unwrap();
+        assert_eq!(layers.merged, expected);
+
+        let origin = |p: &str| layers.origin(&path(p)).unwrap();
+        assert_eq!(
+            origin("devenv.name"),
+            Origin {
+                file: 2,
+                path: path("devenv.name")
+            }
+        );
+        assert_eq!(origin("devenv.packages.0").file, 0);
+        assert_eq!(origin("devenv.packages.1").file, 1);
+        assert_eq!(
+            origin("devenv.packages.2"),
+            Origin {
+                file: 2,
+                path: path("devenv.packages.0")
+            }
+        );
+        assert_eq!(origin("devenv.zed_remote.enabled").file, 1);
+        assert_eq!(origin("devenv.zed_remote.ssh_port").file, 2);
+        assert_eq!(origin("devenv.env.TZ").file, 0);
+        assert_eq!(origin("extends").file, 2);
+    }
+
+    #[test]
+    fn layers_local_overrides_on_top() {
+        let td = TempDir::new().unwrap();
+        fs::write(
+            td.path().join("base.toml"),
+            "[devenv]\npackages = [\"git\"]\n",
+        )
+        .unwrap();
+        let project = td.path().join("devenv.toml");
+        fs::write(
+            &project,
+            "extends = \"base.toml\"\n\n[devenv]\npackages = [\"curl\"]\nzed_remote = { enabled = true, ssh_port = 2222 }\n",
+        )
+        .unwrap();
+        let local = td.path().join(LOCAL_FILENAME);
+        fs::write(
+            &local,
+            "[devenv]\npackages = [\"vim\"]\nssh_public_key = \"~/.ssh/me.pub\"\n\n[devenv.zed_remote]\nssh_port = 2300\n",
+        )
+        .unwrap();
+
+        let layers = load(&project).unwrap();
+        assert_eq!(layers.files.len(), 3);
+        assert_eq!(layers.project, 1);
+        let devenv = layers.merged["devenv"].as_table().unwrap();
+        assert_eq!(
+            devenv["packages"],
+            Value::Array(vec!["git".into(), "curl".into(), "vim".into()])
+        );
+        assert_eq!(devenv["zed_remote"]["ssh_port"].as_integer(), Some(2300));
+        assert_eq!(devenv["zed_remote"]["enabled"].as_bool(), Some(true));
+        assert_eq!(
+            layers.origin(&path("devenv.ssh_public_key")).unwrap().file,
+            2
+        );
+        assert_eq!(layers.origin(&path("extends")).unwrap().file, 1);
+
+        fs::write(&local, "extends = \"base.toml\"\n").unwrap();
+        assert!(load(&project).is_err());
+    }
+
+    #[test]
+    fn annotates_values_with_their_file() {
+        let td = TempDir::new().unwrap();
+        fs::write(
+            td.path().join("base.toml"),
+            "[devenv]\nname = \"base\"\npackages = [\"curl\"]\n",
+        )
+        .unwrap();
+        let project = td.path().join("devenv.toml");
+        fs::write(
+            &project,
+            "extends = \"base.toml\"\n\n[devenv]\nname = \"app\"\npackages = [\"jq\"]\n",
+        )
+        .unwrap();
+        let layers = load(&project).unwrap();
+        let text = "extends = \"base.toml\"\n\n[devenv]\nname = \"app\"\npackages = [\n    \"curl\",\n    \"jq\",\n]\nprovision_as_non_root = false\n";
+        assert_eq!(
+            layers.annotate(text),
+            r#"extends = "base.toml"          # devenv.toml
+
+[devenv]
+name = "app"                   # devenv.toml
+packages = [
+    "curl",                    # base.toml
+    "jq",                      # devenv.toml
+]
+provision_as_non_root = false  # (default)
+"#
+        );
+    }
+
+    #[test]
+    fn rejects_cycles_and_missing_bases() {
+        let td = TempDir::new().unwrap();
+        let a = td.path().join("a.toml");
+        fs::write(&a, "extends = \"b.toml\"\n").unwrap();
+        fs::write(td.path().join("b.toml"), "extends = \"a.toml\"\n").unwrap();
+        let err = load(&a).unwrap_err().to_string();
+        assert_eq!(err, "a.toml extends itself");
+
+        fs::write(&a, "extends = \"missing.toml\"\n").unwrap();
+        assert!(load(&a).is_err());
+        fs::write(&a, "extends = 1\n").unwrap();
+        assert!(load(&a).is_err());
+    }
+
+    #[test]
+    fn diffs_changes_against_the_loaded_config() {
+        let loaded: Table = toml::from_str("a = 1\nlist = [1]\n[t]\nx = 1\ny = 2\n").unwrap();
+        let current: Table =
+            toml::from_str("a = 1\nlist = [1, 2]\nnew = true\n[t]\nx = 1\ny = 3\n").unwrap();
+        let expected: Table = toml::from_str("list = [2]\nnew = true\n[t]\ny = 3\n").unwrap();
+        assert_eq!(diff(&current, &loaded), expected);
+    }
+
+    #[test]
+    fn checks_out_git_bases() {
+        let td = TempDir::new().unwrap();
+        let remote = td.path().join("remote.git");
+        let work = td.path().join("work");
+        let run = |dir: &Path, args: &[&str]| {
+            let output = Command::new("git")
+                .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+                .args(args)
+                .current_dir(dir)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {args:?}");
+            String::from_utf8(output.stdout).unwrap().trim().to_string()
+        };
+        let commit = |v: u32| {
+            fs::write(work.join("devenv/base.toml"), format!("v = {v}\n")).unwrap();
+            run(&work, &["commit", "--quiet", "-am", &v.to_string()]);
+            run(&work, &["rev-parse", "HEAD"])
+        };
+        fs::create_dir_all(work.join("devenv")).unwrap();
+        run(
+            td.path(),
+            &["init", "--quiet", "--bare", "-b", "main", "remote.git"],
+        );
+        run(&work, &["init", "--quiet", "-b", "main"]);
+        fs::write(work.join("devenv/base.toml"), "").unwrap();
+        run(&work, &["add", "."]);
+        run(
+            &work,
+            &["remote", "add", "origin", remote.to_str().unwrap()],
+        );
+        let first = commit(1);
+        run(&work, &["tag", "v1"]);
+        run(&work, &["push", "--quiet", "origin", "main", "v1"]);
+        run(&work, &["checkout", "--quiet", "-b", "develop"]);
+        commit(2);
+        run(&work, &["push", "--quiet", "origin", "develop"]);
+
+        let cache = td.path().join("cache");
+        let base = |rev: Option<&str>| GitBase {
+            git: remote.display().to_string(),
+            rev: rev.map(String::from),
+            path: "devenv/base.toml".into(),
+        };
+        let read = |rev: Option<&str>| {
+            let dir = checkout(&base(rev), &cache).unwrap();
+            fs::read_to_string(dir.join("devenv/base.toml")).unwrap()
+        };
+        assert_eq!(read(None), "v = 1\n");
+        assert_eq!(read(Some("main")), "v = 1\n");
+        assert_eq!(read(Some("develop")), "v = 2\n");
+        assert_eq!(read(Some("v1")), "v = 1\n");
+        assert_eq!(read(Some(&first)), "v = 1\n");
+        assert_eq!(read(Some(&first[..10])), "v = 1\n");
+
+        // Branches are fetched again on each load, each in its own checkout
+        run(&work, &["checkout", "--quiet", "main"]);
+        commit(3);
+        run(&work, &["checkout", "--quiet", "develop"]);
+        commit(4);
+        run(&work, &["push", "--quiet", "origin", "main", "develop"]);
+        assert_eq!(read(None), "v = 3\n");
+        assert_eq!(read(Some("main")), "v = 3\n");
+        assert_eq!(read(Some("develop")), "v = 4\n");
+        assert_eq!(read(Some("v1")), "v = 1\n");
+        assert!(checkout(&base(Some("v9")), &cache).is_err());
+
+        // Without the remote, the last checkout is kept
+        fs::remove_dir_all(&remote).unwrap();
+        assert_eq!(read(Some("develop")), "v = 4\n");
+        assert!(checkout(&base(Some("v3")), &cache).is_err());
+    }
+}
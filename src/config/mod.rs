@@ -0,0 +1,811 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use tracing::warn;
+
+use self::layers::Extends;
+use crate::{
+    detect::Detector,
+    docker::{MountKind, file::OsFamily, service::parse_port},
+};
+
+pub mod layers;
+pub mod validate;
+
+const FILENAME: &str = "devenv.toml";
+/// Uncommitted personal overrides, layered on top of devenv.toml
+pub const LOCAL_FILENAME: &str = "devenv.local.toml";
+const DEFAULT_DOCKERFILE: &str = ".devenv/Dockerfile";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Config this one builds on; lists append to it, tables merge with it
+    /// and other values override it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<Extends>,
+    pub devenv: DevEnvConfig,
+    #[serde(skip)]
+    pub path: PathBuf,
+    /// The merged config as loaded, when it has bases or local overrides;
+    /// `save` writes only what changed since
+    #[serde(skip)]
+    loaded: Option<toml::Table>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DevEnvConfig {
+    /// Unique environment name (defaults to directory name)
+    pub name: String,
+    /// Base Docker image to use (auto-detected if empty)
+    pub image: String,
+    /// OS family of the base image (inferred from `image` if unset)
+    pub os_family: Option<OsFamily>,
+    /// Path to SSH private key to mount into the container (optional)
+    pub ssh_private_key: Option<String>,
+    /// Extra OS packages to install (apt-based)
+    pub packages: Vec<String>,
+    /// Commands to run after container start (provisioning)
+    pub commands: Vec<String>,
+    /// Dockerfile instructions inserted before OS packages are installed
+    pub pre_install: Option<String>,
+    /// Dockerfile instructions inserted after OS packages and the user are set up
+    pub post_install: Option<String>,
+    /// Dockerfile instructions inserted at the end of the Dockerfile
+    #[serde(rename = "final")]
+    pub final_steps: Option<String>,
+    /// Project-relative path to a mustache template replacing the built-in Dockerfile
+    pub dockerfile_template: Option<String>,
+    /// Project-relative path of the generated Dockerfile (defaults to `.devenv/Dockerfile`)
+    pub dockerfile: Option<String>,
+    /// Project-relative build context directory (defaults to the project root)
+    pub context: Option<String>,
+    /// Multi-stage build target
+    pub target: Option<String>,
+    /// Build arguments; values may reference host environment variables (`${VAR}`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub build_args: BTreeMap<String, String>,
+    /// BuildKit secrets, mapping secret ids to host files (never stored in image layers)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub build_secrets: BTreeMap<String, String>,
+    /// Optional Zed remote configuration
+    pub zed_remote: Option<ZedRemote>,
+    /// Optional path to a public key to add to authorized_keys inside the container
+    pub ssh_public_key: Option<String>,
+    /// Optional non-root user configuration for container login/ownership
+    pub user_name: Option<String>,
+    pub user_uid: Option<u32>,
+    pub user_gid: Option<u32>,
+    /// Run provisioning commands as non-root user if available
+    pub provision_as_non_root: bool,
+    /// Project-relative dotenv file whose variables are set in the container
+    pub env_file: Option<String>,
+    /// Container environment variables, overriding `env_file`; values may
+    /// reference host environment variables (`${VAR}`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Container ports published on the host
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<Port>,
+    /// CPU, memory and process limits for the dev container
+    pub resources: Option<Resources>,
+    /// Extra mounts besides the project at /workspace
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mounts: Vec<Mount>,
+    /// Service containers (databases, caches, …) started alongside the environment
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<Service>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ZedRemote {
+    pub enabled: bool,
+    /// SSH port published on the host; defaults to 2222
+    pub ssh_port: Option<u16>,
+    /// SSH username (container user); defaults to root
+    pub ssh_user: Option<String>,
+}
+
+/// A container port published on the host, written in Docker's short
+/// `[[IP:]HOST:]CONTAINER[/PROTO]` syntax or as a table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Port {
+    pub target: u16,
+    /// Host port; Docker picks a free one when unset
+    pub published: Option<u16>,
+    pub host_ip: Option<String>,
+    /// `tcp` or `udp`
+    pub protocol: String,
+}
+
+// Table form of a port
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LongPort {
+    target: u16,
+    published: Option<u16>,
+    host_ip: Option<String>,
+    #[serde(default = "tcp")]
+    protocol: String,
+}
+
+fn tcp() -> String {
+    "tcp".into()
+}
+
+impl Port {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (key, binding) = parse_port(spec)?;
+        let (target, protocol) = key.split_once('/').unwrap_or((&key, "tcp"));
+        Ok(Port {
+            target: target.parse()?,
+            published: binding.host_port.map(|p| p.parse()).transpose()?,
+            host_ip: binding.host_ip,
+            protocol: protocol.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ip) = &self.host_ip {
+            write!(f, "{ip}:")?;
+        }
+        match self.published {
+            Some(port) => write!(f, "{port}:")?,
+            None if self.host_ip.is_some() => f.write_str(":")?,
+            None => {}
+        }
+        write!(f, "{}", self.target)?;
+        if self.protocol != "tcp" {
+            write!(f, "/{}", self.protocol)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Port {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Port {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Port;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a port such as \"8080:80\" or a table with `target`")
+            }
+
+            fn visit_str<E: de::Error>(self, spec: &str) -> Result<Port, E> {
+                Port::parse(spec).map_err(|e| E::custom(format!("invalid port `{spec}`: {e}")))
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Port, A::Error> {
+                let long = LongPort::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                if !matches!(long.protocol.as_str(), "tcp" | "udp") {
+                    return Err(de::Error::custom(format!(
+                        "unknown protocol `{}`, expected `tcp` or `udp`",
+                        long.protocol
+                    )));
+                }
+                Ok(Port {
+                    target: long.target,
+                    published: long.published,
+                    host_ip: long.host_ip,
+                    protocol: long.protocol,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// Limits for the dev container. Sizes are written like `512m` or `4g`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Resources {
+    /// Number of CPUs, e.g. `2` or `1.5`
+    pub cpus: Option<f64>,
+    pub memory: Option<String>,
+    /// Memory plus swap, or `-1` for unlimited swap
+    pub memory_swap: Option<String>,
+    pub pids_limit: Option<i64>,
+    /// Size of /dev/shm
+    pub shm_size: Option<String>,
+    /// Limits by name (`nofile`, `nproc`, …): a number, or `{ soft, hard }`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ulimits: BTreeMap<String, Ulimit>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Ulimit {
+    Both(i64),
+    Split { soft: i64, hard: i64 },
+}
+
+impl Ulimit {
+    pub fn soft_hard(self) -> (i64, i64) {
+        match self {
+            Ulimit::Both(limit) => (limit, limit),
+            Ulimit::Split { soft, hard } => (soft, hard),
+        }
+    }
+}
+
+/// An extra mount in the dev container
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Mount {
+    /// `bind` (the default), `volume` or `tmpfs`
+    #[serde(rename = "type", default)]
+    pub kind: MountKind,
+    /// Host path for `bind` (`~` and `${VAR}` are expanded, relative paths
+    /// resolve against the project), volume name for `volume`, unused for `tmpfs`
+    pub source: Option<String>,
+    /// Absolute path in the container
+    pub target: String,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// A service container on the environment's network, reachable by `name`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Service {
+    pub name: String,
+    pub image: String,
+    /// Environment variables; values may reference host environment variables (`${VAR}`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Ports published on the host: `CONTAINER`, `HOST:CONTAINER` or `IP:HOST:CONTAINER`,
+    /// optionally suffixed with `/udp`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<String>,
+    /// `SOURCE:TARGET[:ro]`, where a source starting with `.`, `/` or `~` is a host
+    /// path and anything else a named volume
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<String>,
+    /// Services that must be healthy before this one starts; they must be listed earlier
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    pub healthcheck: Option<Healthcheck>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Healthcheck {
+    /// Shell command that exits 0 when the service is ready
+    pub test: String,
+    /// Durations such as `5s`, `1m30s` or `500ms`
+    pub interval: Option<String>,
+    pub timeout: Option<String>,
+    pub start_period: Option<String>,
+    pub retries: Option<u32>,
+}
+
+impl DevEnvConfig {
+    pub fn os_family(&self) -> OsFamily {
+        self.os_family
+            .unwrap_or_else(|| OsFamily::from_image(&self.image))
+    }
+
+    /// Non-root container user, preferring `user_name` over `zed_remote.ssh_user`
+    pub fn non_root_user(&self) -> Option<&str> {
+        self.user_name
+            .as_deref()
+            .or_else(|| self.zed_remote.as_ref().and_then(|z| z.ssh_user.as_deref()))
+            .filter(|u| *u != "root")
+    }
+}
+
+impl Config {
+    /// Directory containing devenv.toml
+    pub fn project_dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new("."))
+    }
+
+    pub fn dockerfile_path(&self) -> PathBuf {
+        let rel = self
+            .devenv
+            .dockerfile
+            .as_deref()
+            .unwrap_or(DEFAULT_DOCKERFILE);
+        self.project_dir().join(rel)
+    }
+
+    pub fn context_dir(&self) -> PathBuf {
+        match &self.devenv.context {
+            Some(ctx) => self.project_dir().join(ctx),
+            None => self.project_dir().to_path_buf(),
+        }
+    }
+
+    pub fn exists(path: impl AsRef<Path>) -> bool {
+        make_path(path).exists()
+    }
+
+    /// Load devenv.toml merged with the configs it extends and with
+    /// devenv.local.toml
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = make_path(path);
+        let layers = layers::load(&path)?;
+        let mut cfg = validate::parse(&layers)?;
+        cfg.path = path;
+        if layers.files.len() > 1 {
+            cfg.loaded = Some(layers.merged);
+        }
+        Ok(cfg)
+    }
+
+    /// Create a config for the project at `cwd`, picking the image and
+    /// packages with `detector`
+    pub fn create(cwd: impl AsRef<Path>, detector: &Detector) -> Result<Self> {
+        let cwd = cwd.as_ref();
+        Self::create_with(cwd, |devenv| {
+            // Try and set a sane Docker image, adding toolchains for any other
+            // ecosystems found in the workspace
+            let detection = detector.detect(cwd);
+            devenv.image = detection.image;
+            devenv.packages = detection.packages;
+            devenv.post_install = detection.post_install;
+            for warning in detection.warnings {
+                warn!("Warning: {warning}");
+            }
+            Ok(())
+        })
+    }
+
+    /// Create a config for the project at `cwd`, named after the directory,
+    /// with `fill` setting the remaining fields
+    pub fn create_with(
+        cwd: impl AsRef<Path>,
+        fill: impl FnOnce(&mut DevEnvConfig) -> Result<()>,
+    ) -> Result<Self> {
+        let cwd = cwd.as_ref();
+        let cfg_path = make_path(cwd);
+
+        // Sanity checks
+        if !cwd.is_dir() {
+            bail!("Path must be a directory");
+        } else if cfg_path.exists() {
+            bail!("Config file already exists");
+        }
+
+        let mut this = Config {
+            extends: None,
+            devenv: Default::default(),
+            path: cfg_path,
+            loaded: None,
+        };
+
+        // Set project name to directory name, lowercased for the image name
+        this.devenv.name = cwd
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("new_project")
+            .to_ascii_lowercase();
+        fill(&mut this.devenv)?;
+        this.save()?;
+
+        Ok(this)
+    }
+
+    /// Write the config back to its devenv.toml. With bases or local
+    /// overrides, only the changes since loading are added to the file, so it
+    /// doesn't absorb the other layers.
+    pub fn save(&self) -> Result<()> {
+        let toml_str = match &self.loaded {
+            None => toml::to_string_pretty(self)?,
+            Some(loaded) => {
+                let own = fs::read_to_string(&self.path)
+                    .with_context(|| format!("Reading {}", self.path.display()))?;
+                let mut own: toml::Table = toml::from_str(&own)?;
+                let changes = layers::diff(&toml::Table::try_from(self)?, loaded);
+                if changes.is_empty() {
+                    return Ok(());
+                }
+                layers::merge_into(&mut own, changes);
+                toml::to_string_pretty(&own)?
+            }
+        };
+        fs::write(&self.path, toml_str).with_context(|| format!("Writing {}", self.path.display()))
+    }
+}
+
+/// Path of devenv.toml, given the project directory or the file itself
+pub fn make_path(path: impl AsRef<Path>) -> PathBuf {
+    match path.as_ref().file_name() {
+        Some(name) if name.to_str() == Some(FILENAME) => path.as_ref().to_path_buf(),
+        _ => path.as_ref().join(FILENAME),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detect::DEFAULT_DEPTH;
+    use tempfile::TempDir;
+
+    #[test]
+    fn exists_and_open_work_with_dir_and_file_paths() {
+        let td = TempDir::new().unwrap();
+        // Initially, no config exists
+        assert!(!Config::exists(td.path()));
+        // Create minimal config file
+        let cfg_path = td.path().join(FILENAME);
+        let content = r#"[devenv]
+name = "sample"
+image = "debian:bookworm-slim"
+packages = []
+commands = []
+provision_as_non_root = false
+"#;
+        std::fs::write(&cfg_path, content).unwrap();
+        assert!(Config::exists(td.path()));
+        // Can open via directory or full file path
+        let a = Config::open(td.path()).unwrap();
+        let b = Config::open(&cfg_path).unwrap();
+        assert_eq!(a.devenv.name, "sample");
+        assert_eq!(b.devenv.image, "debian:bookworm-slim");
+    }
+
+    #[test]
+    fn os_family_is_inferred_or_overridden() {
+        let td = TempDir::new().unwrap();
+        let cfg_path = td.path().join(FILENAME);
+        std::fs::write(
+            &cfg_path,
+            "[devenv]\nname = \"a\"\nimage = \"node:20-alpine\"\npackages = []\ncommands = []\nprovision_as_non_root = false\n",
+        )
+        .unwrap();
+        let cfg = Config::open(td.path()).unwrap();
+        assert_eq!(cfg.devenv.os_family(), OsFamily::Alpine);
+
+        std::fs::write(
+            &cfg_path,
+            "[devenv]\nname = \"a\"\nimage = \"registry.local/base:1\"\nos_family = \"rhel\"\npackages = []\ncommands = []\nprovision_as_non_root = false\n",
+        )
+        .unwrap();
+        let cfg = Config::open(td.path()).unwrap();
+        assert_eq!(cfg.devenv.os_family(), OsFamily::Rhel);
+    }
+
+    #[test]
+    fn create_sets_defaults_and_detects_image() {
+        let td = TempDir::new().unwrap();
+        let dir = td.path().join("myproj");
+        std::fs::create_dir_all(&dir).unwrap();
+        // No recognizable files -> default image
+        let cfg = Config::create(&dir, &Detector::new(DEFAULT_DEPTH)).unwrap();
+        assert_eq!(cfg.devenv.name, "myproj");
+        assert_eq!(cfg.devenv.image, "debian:bookworm-slim");
+        assert!(cfg.devenv.packages.is_empty());
+        assert!(cfg.devenv.commands.is_empty());
+        assert!(!cfg.devenv.provision_as_non_root);
+        assert!(cfg.path.exists());
+
+        // Recognizable language file -> detected image
+        let dir2 = td.path().join("rustproj");
+        std::fs::create_dir_all(&dir2).unwrap();
+        std::fs::write(dir2.join("Cargo.toml"), "[package]\nname='x'\n").unwrap();
+        let cfg2 = Config::create(&dir2, &Detector::new(DEFAULT_DEPTH)).unwrap();
+        assert_eq!(cfg2.devenv.name, "rustproj");
+        assert_eq!(cfg2.devenv.image, "rust:trixie");
+        assert_eq!(cfg2.path, dir2.join(FILENAME));
+    }
+
+    #[test]
+    fn build_paths_default_and_override() {
+        let td = TempDir::new().unwrap();
+        let cfg_path = td.path().join(FILENAME);
+        std::fs::write(
+            &cfg_path,
+            "[devenv]\nname = \"a\"\nimage = \"debian\"\npackages = []\ncommands = []\nprovision_as_non_root = false\n",
+        )
+        .unwrap();
+        let cfg = Config::open(td.path()).unwrap();
+        assert_eq!(cfg.path, cfg_path);
+        assert_eq!(cfg.project_dir(), td.path());
+        assert_eq!(cfg.dockerfile_path(), td.path().join(".devenv/Dockerfile"));
+        assert_eq!(cfg.context_dir(), td.path());
+
+        std::fs::write(
+            &cfg_path,
+            "[devenv]\nname = \"a\"\nimage = \"debian\"\ndockerfile = \"docker/dev.Dockerfile\"\ncontext = \"app\"\ntarget = \"dev\"\npackages = []\ncommands = []\nprovision_as_non_root = false\n",
+        )
+        .unwrap();
+        let cfg = Config::open(&cfg_path).unwrap();
+        assert_eq!(
+            cfg.dockerfile_path(),
+            td.path().join("docker/dev.Dockerfile")
+        );
+        assert_eq!(cfg.context_dir(), td.path().join("app"));
+        assert_eq!(cfg.devenv.target.as_deref(), Some("dev"));
+    }
+
+    #[test]
+    fn parses_build_args_and_secrets() {
+        let td = TempDir::new().unwrap();
+        let content = r#"[devenv]
+name = "a"
+image = "rust:trixie"
+packages = []
+commands = []
+provision_as_non_root = false
+
+[devenv.build_args]
+RUST_VERSION = "1.82"
+NPM_USER = "${USER}"
+
+[devenv.build_secrets]
+cargo_token = "~/.cargo/credentials.toml"
+"#;
+        std::fs::write(td.path().join(FILENAME), content).unwrap();
+        let cfg = Config::open(td.path()).unwrap();
+        assert_eq!(cfg.devenv.build_args["RUST_VERSION"], "1.82");
+        assert_eq!(cfg.devenv.build_args["NPM_USER"], "${USER}");
+        assert_eq!(
+            cfg.devenv.build_secrets["cargo_token"],
+            "~/.cargo/credentials.toml"
+        );
+
+        // Empty tables are not written out on create
+        let dir = td.path().join("sub");
+        std::fs::create_dir_all(&dir).unwrap();
+        let cfg = Config::create(&dir, &Detector::new(DEFAULT_DEPTH)).unwrap();
+        let written = std::fs::read_to_string(cfg.path).unwrap();
+        assert!(!written.contains("build_args"));
+        assert!(!written.contains("build_secrets"));
+    }
+
+    #[test]
+    fn parses_services() {
+        let td = TempDir::new().unwrap();
+        let content = r#"[devenv]
+name = "a"
+image = "rust:trixie"
+packages = []
+commands = []
+provision_as_non_root = false
+
+[[devenv.services]]
+name = "postgres"
+image = "postgres:16"
+env = { POSTGRES_PASSWORD = "dev" }
+ports = ["5432:5432"]
+volumes = ["pgdata:/var/lib/postgresql/data"]
+healthcheck = { test = "pg_isready -U postgres", interval = "2s", retries = 10 }
+
+[[devenv.services]]
+name = "redis"
+image = "redis:7"
+"#;
+        std::fs::write(td.path().join(FILENAME), content).unwrap();
+        let cfg = Config::open(td.path()).unwrap();
+        let [pg, redis] = &cfg.devenv.services[..] else {
+            panic!("expected two services");
+        };
+        assert_eq!(pg.env["POSTGRES_PASSWORD"], "dev");
+        assert_eq!(pg.ports, vec!["5432:5432"]);
+        let hc = pg.healthcheck.as_ref().unwrap();
+        assert_eq!(hc.interval.as_deref(), Some("2s"));
+        assert_eq!(hc.retries, Some(10));
+        assert!(redis.env.is_empty() && redis.healthcheck.is_none());
+
+        // Typos in a service are rejected rather than ignored
+        let typo = content.replace("ports =", "port =");
+        std::fs::write(td.path().join(FILENAME), typo).unwrap();
+        assert!(Config::open(td.path()).is_err());
+    }
+
+    #[test]
+    fn parses_env() {
+        let td = TempDir::new().unwrap();
+        let content = r#"[devenv]
+name = "a"
+image = "rust:trixie"
+packages = []
+commands = []
+provision_as_non_root = false
+env_file = ".env"
+
+[devenv.env]
+RUST_LOG = "debug"
+DATABASE_URL = "postgres://postgres:${PGPASSWORD}@db/app"
+"#;
+        std::fs::write(td.path().join(FILENAME), content).unwrap();
+        let cfg = Config::open(td.path()).unwrap();
+        assert_eq!(cfg.devenv.env_file.as_deref(), Some(".env"));
+        assert_eq!(cfg.devenv.env["RUST_LOG"], "debug");
+
+        // Written back unchanged
+        cfg.save().unwrap();
+        let saved = Config::open(td.path()).unwrap();
+        assert_eq!(saved.devenv.env, cfg.devenv.env);
+    }
+
+    #[test]
+    fn parses_ports() {
+        let td = TempDir::new().unwrap();
+        let content = r#"[devenv]
+name = "a"
+image = "rust:trixie"
+packages = []
+commands = []
+provision_as_non_root = false
+ports = [
+    "3000",
+    "127.0.0.1:5432:5432",
+    "9229/udp",
+    { target = 8080, published = 80, host_ip = "127.0.0.1" },
+    { target = 6006, host_ip = "127.0.0.1" },
+]
+"#;
+        std::fs::write(td.path().join(FILENAME), content).unwrap();
+        let cfg = Config::open(td.path()).unwrap();
+        let ports: Vec<_> = cfg.devenv.ports.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            ports,
+            vec![
+                "3000",
+                "127.0.0.1:5432:5432",
+                "9229/udp",
+                "127.0.0.1:80:8080",
+                "127.0.0.1::6006"
+            ]
+        );
+        // Every form reads back the same
+        for port in &cfg.devenv.ports {
+            assert_eq!(&Port::parse(&port.to_string()).unwrap(), port);
+        }
+
+        // Invalid ports are rejected when loading
+        for bad in [
+            "\"3000:http\"",
+            "\"80/sctp\"",
+            "{ published = 80 }",
+            "{ target = 80, protocol = \"sctp\" }",
+        ] {
+            let invalid = content.replace("\"3000\"", bad);
+            std::fs::write(td.path().join(FILENAME), invalid).unwrap();
+            assert!(Config::open(td.path()).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn parses_resources() {
+        let td = TempDir::new().unwrap();
+        let content = r#"[devenv]
+name = "a"
+image = "rust:trixie"
+packages = []
+commands = []
+provision_as_non_root = false
+
+[devenv.resources]
+cpus = 2
+memory = "4g"
+memory_swap = "-1"
+pids_limit = 512
+shm_size = "1g"
+ulimits = { nofile = 65536, nproc = { soft = 1024, hard = 2048 } }
+"#;
+        std::fs::write(td.path().join(FILENAME), content).unwrap();
+        let cfg = Config::open(td.path()).unwrap();
+        let res = cfg.devenv.resources.unwrap();
+        assert_eq!(res.cpus, Some(2.0));
+        assert_eq!(res.memory_swap.as_deref(), Some("-1"));
+        assert_eq!(res.ulimits["nofile"].soft_hard(), (65536, 65536));
+        assert_eq!(res.ulimits["nproc"].soft_hard(), (1024, 2048));
+    }
+
+    #[test]
+    fn parses_mounts() {
+        let td = TempDir::new().unwrap();
+        let content = r#"[devenv]
+name = "a"
+image = "rust:trixie"
+packages = []
+commands = []
+provision_as_non_root = false
+
+[[devenv.mounts]]
+source = "~/.cargo/registry"
+target = "/usr/local/cargo/registry"
+
+[[devenv.mounts]]
+type = "volume"
+source = "node_modules"
+target = "/workspace/node_modules"
+
+[[devenv.mounts]]
+type = "tmpfs"
+target = "/tmp"
+read_only = true
+"#;
+        std::fs::write(td.path().join(FILENAME), content).unwrap();
+        let cfg = Config::open(td.path()).unwrap();
+        let kinds: Vec<_> = cfg.devenv.mounts.iter().map(|m| m.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![MountKind::Bind, MountKind::Volume, MountKind::Tmpfs]
+        );
+        assert!(cfg.devenv.mounts[2].read_only && cfg.devenv.mounts[2].source.is_none());
+
+        let typo = content.replace("\"volume\"", "\"volumes\"");
+        std::fs::write(td.path().join(FILENAME), typo).unwrap();
+        assert!(Config::open(td.path()).is_err());
+    }
+
+    #[test]
+    fn opens_and_saves_configs_with_bases() {
+        let td = TempDir::new().unwrap();
+        std::fs::write(
+            td.path().join("base.toml"),
+            "[devenv]\nname = \"base\"\nimage = \"debian\"\npackages = [\"git\"]\ncommands = []\nprovision_as_non_root = false\n",
+        )
+        .unwrap();
+        let own = "extends = \"base.toml\"\n\n[devenv]\nname = \"app\"\npackages = [\"jq\"]\n";
+        std::fs::write(td.path().join(FILENAME), own).unwrap();
+        let mut cfg = Config::open(td.path()).unwrap();
+        assert_eq!(cfg.extends, Some(Extends::Path("base.toml".into())));
+        assert_eq!(cfg.devenv.name, "app");
+        assert_eq!(cfg.devenv.image, "debian");
+        assert_eq!(cfg.devenv.packages, ["git", "jq"]);
+
+        cfg.devenv.commands.push("make".into());
+        cfg.save().unwrap();
+        let saved = std::fs::read_to_string(td.path().join(FILENAME)).unwrap();
+        assert_eq!(
+            saved,
+            "extends = \"base.toml\"\n\n[devenv]\ncommands = [\"make\"]\nname = \"app\"\npackages = [\"jq\"]\n"
+        );
+        let cfg = Config::open(td.path()).unwrap();
+        assert_eq!(cfg.devenv.commands, ["make"]);
+        assert_eq!(cfg.devenv.packages, ["git", "jq"]);
+    }
+
+    #[test]
+    fn local_overrides_stay_out_of_devenv_toml() {
+        let td = TempDir::new().unwrap();
+        let own = "[devenv]\nname = \"app\"\nimage = \"debian\"\npackages = [\"git\"]\ncommands = []\nprovision_as_non_root = false\n\n[devenv.zed_remote]\nenabled = true\n";
+        std::fs::write(td.path().join(FILENAME), own).unwrap();
+        std::fs::write(
+            td.path().join(LOCAL_FILENAME),
+            "[devenv]\npackages = [\"helix\"]\nssh_public_key = \"~/.ssh/work.pub\"\n\n[devenv.zed_remote]\nssh_port = 2300\n",
+        )
+        .unwrap();
+        let mut cfg = Config::open(td.path()).unwrap();
+        assert_eq!(cfg.devenv.packages, ["git", "helix"]);
+        assert_eq!(
+            cfg.devenv.ssh_public_key.as_deref(),
+            Some("~/.ssh/work.pub")
+        );
+        let zed = cfg.devenv.zed_remote.as_ref().unwrap();
+        assert!(zed.enabled);
+        assert_eq!(zed.ssh_port, Some(2300));
+
+        cfg.devenv.commands.push("make".into());
+        cfg.save().unwrap();
+        let saved = std::fs::read_to_string(td.path().join(FILENAME)).unwrap();
+        let expected = own.replace("commands = []", "commands = [\"make\"]");
+        assert_eq!(
+            toml::from_str::<toml::Table>(&saved).unwrap(),
+            toml::from_str::<toml::Table>(&expected).unwrap()
+        );
+    }
+}
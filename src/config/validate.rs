@@ -0,0 +1,999 @@
+use std::{
+    fmt::{self, Write as _},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use toml::{
+    Spanned,
+    de::{DeTable, DeValue},
+};
+
+use super::{
+    Config, DevEnvConfig, Port, Resources,
+    layers::{self, Layers},
+};
+use crate::docker::{
+    MountKind,
+    resources::parse_size,
+    service::{parse_duration, parse_port},
+};
+
+/// Docker's lower bound for `--memory`
+const MIN_MEMORY: i64 = 6 << 20;
+
+/// A problem found in devenv.toml, with the byte range it refers to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Option<Range<usize>>,
+    pub help: Option<String>,
+    /// Index of the file the span is in, for layered configs
+    pub file: usize,
+}
+
+/// The result of validating a devenv.toml
+#[derive(Debug)]
+pub struct Report {
+    pub path: PathBuf,
+    /// Paths and contents of devenv.toml and the configs it extends
+    files: Vec<(PathBuf, String)>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// A config file that is not valid TOML
+#[derive(Debug)]
+pub struct Invalid {
+    pub path: PathBuf,
+    pub source: String,
+    pub diagnostic: Diagnostic,
+}
+
+impl Invalid {
+    pub fn new(path: &Path, source: &str, err: &toml::de::Error) -> Self {
+        Invalid {
+            path: path.to_path_buf(),
+            source: source.to_string(),
+            diagnostic: parse_error(err),
+        }
+    }
+}
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.path.display().to_string();
+        write!(
+            f,
+            "{name} is invalid\n{}",
+            render(&name, &self.source, &self.diagnostic)
+        )
+    }
+}
+
+impl std::error::Error for Invalid {}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// Render every diagnostic with its location and the offending line
+    pub fn render(&self) -> String {
+        self.diagnostics
+            .iter()
+            .map(|d| {
+                let (path, source) = &self.files[d.file];
+                render(&path.display().to_string(), source, d)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Validate the devenv.toml at `path`: its syntax, unknown keys and the
+/// values devenv would otherwise only reject when starting the environment
+pub fn validate(path: &Path) -> Result<Report> {
+    let path = super::make_path(path);
+    let layers = layers::load(&path);
+    report(path, layers, is_root())
+}
+
+fn report(path: PathBuf, layers: Result<Layers>, is_root: bool) -> Result<Report> {
+    let layers = match layers {
+        Ok(layers) => layers,
+        Err(e) => {
+            let invalid = e.downcast::<Invalid>()?;
+            return Ok(Report {
+                path,
+                files: vec![(invalid.path, invalid.source)],
+                diagnostics: vec![invalid.diagnostic],
+            });
+        }
+    };
+    let diagnostics = check(&layers, is_root);
+    Ok(Report {
+        path,
+        files: layers
+            .files
+            .into_iter()
+            .map(|f| (f.path, f.source))
+            .collect(),
+        diagnostics,
+    })
+}
+
+/// Deserialize the merged config, rendering any error with its location
+pub fn parse(layers: &Layers) -> Result<Config> {
+    deserialize(layers).map_err(|d| {
+        let file = &layers.files[d.file];
+        let name = file.path.display().to_string();
+        anyhow!("{name} is invalid\n{}", render(&name, &file.source, &d))
+    })
+}
+
+// A single file is deserialized from its source so errors point into it. A
+// merged config has no spans, so unknown keys are looked up in its files.
+fn deserialize(layers: &Layers) -> Result<Config, Diagnostic> {
+    if let [file] = layers.files.as_slice() {
+        return toml::from_str(&file.source).map_err(|e| parse_error(&e));
+    }
+    toml::Value::Table(layers.merged.clone())
+        .try_into()
+        .map_err(|e| {
+            let mut d = parse_error(&e);
+            d.file = layers.project;
+            if let Some(key) = d.message.strip_prefix("unknown field `") {
+                let key = key.trim_end_matches('`');
+                let found = layers.files.iter().enumerate().rev().find_map(|(i, f)| {
+                    let doc = DeTable::parse(&f.source).ok()?;
+                    Some((i, find_key(doc.get_ref(), key)?))
+                });
+                if let Some((file, span)) = found {
+                    d.file = file;
+                    d.span = Some(span);
+                }
+            }
+            d
+        })
+}
+
+// Turn a TOML or schema error into a diagnostic, suggesting the closest known
+// key for unknown ones
+fn parse_error(err: &toml::de::Error) -> Diagnostic {
+    let message = err.message().trim().to_string();
+    let Some(rest) = message.strip_prefix("unknown field `") else {
+        return Diagnostic {
+            message,
+            span: err.span(),
+            help: None,
+            file: 0,
+        };
+    };
+    // "unknown field `pakages`, expected one of `name`, `image`, ..."
+    let unknown = rest.split('`').next().unwrap_or_default();
+    let expected: Vec<&str> = rest.split('`').skip(2).step_by(2).collect();
+    let help = match did_you_mean(unknown, &expected) {
+        Some(known) => format!("did you mean `{known}`?"),
+        None => format!("expected one of {}", expected.join(", ")),
+    };
+    Diagnostic {
+        message: format!("unknown field `{unknown}`"),
+        span: err.span(),
+        help: Some(help),
+        file: 0,
+    }
+}
+
+// Semantic checks run on the merged config; each problem points into the
+// file its value came from
+fn check(layers: &Layers, is_root: bool) -> Vec<Diagnostic> {
+    let cfg = match deserialize(layers) {
+        Ok(cfg) => cfg,
+        Err(d) => return vec![d],
+    };
+    let docs: Vec<_> = layers
+        .files
+        .iter()
+        .map(|f| DeTable::parse(&f.source).ok())
+        .collect();
+    semantic(&cfg.devenv, is_root)
+        .into_iter()
+        .map(|(path, message, help)| {
+            let (file, path) = match layers.origin(&path) {
+                Some(origin) => (origin.file, origin.path),
+                None => (layers.project, path),
+            };
+            let path: Vec<&str> = path.iter().map(String::as_str).collect();
+            Diagnostic {
+                message,
+                span: docs[file]
+                    .as_ref()
+                    .and_then(|d| find_span(d.get_ref(), &path)),
+                help,
+                file,
+            }
+        })
+        .collect()
+}
+
+type Problem = (Vec<String>, String, Option<String>);
+
+// Checks on values that parse but would fail later; each problem names the
+// key path it refers to
+fn semantic(dev: &DevEnvConfig, is_root: bool) -> Vec<Problem> {
+    let mut problems: Vec<Problem> = Vec::new();
+    let mut problem = |path: &[&str], message: String, help: Option<&str>| {
+        problems.push((
+            path.iter().map(|p| p.to_string()).collect(),
+            message,
+            help.map(String::from),
+        ));
+    };
+
+    if dev.name.trim().is_empty() {
+        problem(
+            &["devenv", "name"],
+            "`name` must not be empty".into(),
+            Some("the environment name is used for its container and image names"),
+        );
+    } else if !valid_name(&dev.name) || dev.name.contains(|c: char| c.is_ascii_uppercase()) {
+        // Image repositories must be lowercase
+        problem(
+            &["devenv", "name"],
+            format!("`{}` is not a valid environment name", dev.name),
+            Some(
+                "use lowercase letters, digits, `_`, `.` and `-`, starting with a letter or digit",
+            ),
+        );
+    }
+
+    if dev.image.is_empty() {
+        if dev.dockerfile_template.is_none() {
+            problem(
+                &["devenv", "image"],
+                "`image` must not be empty".into(),
+                None,
+            );
+        }
+    } else if let Err(e) = check_image(&dev.image) {
+        problem(
+            &["devenv", "image"],
+            format!("`{}` is not a valid image reference: {e}", dev.image),
+            None,
+        );
+    }
+
+    if dev.user_uid == Some(0) {
+        problem(
+            &["devenv", "user_uid"],
+            "`user_uid` must not be 0".into(),
+            Some("uid 0 is root; remove `user_name` to work as root"),
+        );
+    }
+
+    if let Some(z) = &dev.zed_remote
+        && let Some(port) = z.ssh_port
+    {
+        if port == 0 {
+            problem(
+                &["devenv", "zed_remote", "ssh_port"],
+                "`ssh_port` must not be 0".into(),
+                None,
+            );
+        } else if port < 1024 && !is_root {
+            problem(
+                &["devenv", "zed_remote", "ssh_port"],
+                format!("`ssh_port` {port} is a privileged port"),
+                Some("ports below 1024 can only be published by root; the default is 2222"),
+            );
+        }
+    }
+
+    let ssh_port = dev
+        .zed_remote
+        .as_ref()
+        .filter(|z| z.enabled)
+        .map(|z| z.ssh_port.unwrap_or(2222));
+    for (i, port) in dev.ports.iter().enumerate() {
+        let idx = i.to_string();
+        let at = ["devenv", "ports", idx.as_str()];
+        let Some(published) = port.published else {
+            continue;
+        };
+        let same_host = |p: &Port| {
+            p.published == Some(published)
+                && p.protocol == port.protocol
+                && p.host_ip == port.host_ip
+        };
+        if published == 0 {
+            problem(&at, "published port must not be 0".into(), None);
+        } else if dev.ports[..i].iter().any(same_host) {
+            problem(
+                &at,
+                format!("host port {published} is published twice"),
+                None,
+            );
+        } else if ssh_port == Some(published) && port.protocol == "tcp" {
+            problem(
+                &at,
+                format!("host port {published} is the Zed remote `ssh_port`"),
+                None,
+            );
+        } else if published < 1024 && !is_root {
+            problem(
+                &at,
+                format!("host port {published} is a privileged port"),
+                Some("ports below 1024 can only be published by root"),
+            );
+        }
+    }
+
+    if let Some(res) = &dev.resources {
+        check_resources(res, &mut problem);
+    }
+
+    for key in dev.env.keys() {
+        if key.is_empty() || key.contains(|c: char| c == '=' || c.is_whitespace()) {
+            problem(
+                &["devenv", "env", key],
+                format!("`{key}` is not a valid environment variable name"),
+                None,
+            );
+        }
+    }
+
+    for (i, mount) in dev.mounts.iter().enumerate() {
+        let idx = i.to_string();
+        let at = |key: &'static str| ["devenv", "mounts", idx.as_str(), key].map(String::from);
+        let mut problem = |key: &'static str, message: String| {
+            problems.push((at(key).to_vec(), message, None));
+        };
+        let target = mount.target.trim_end_matches('/');
+        if !mount.target.starts_with('/') {
+            problem(
+                "target",
+                format!("mount target `{}` must be an absolute path", mount.target),
+            );
+        } else if target == "/workspace" {
+            problem("target", "`/workspace` is the project mount".into());
+        } else if dev.mounts[..i]
+            .iter()
+            .any(|m| m.target.trim_end_matches('/') == target)
+        {
+            problem("target", format!("`{}` is mounted twice", mount.target));
+        }
+        match (mount.kind, mount.source.as_deref()) {
+            (MountKind::Bind, None) => {
+                problem("target", "a bind mount needs a `source`".into());
+            }
+            (MountKind::Volume, Some(name)) if !valid_name(name) => {
+                problem("source", format!("`{name}` is not a valid volume name"));
+            }
+            (MountKind::Tmpfs, Some(_)) => {
+                problem("source", "a tmpfs mount has no `source`".into());
+            }
+            _ => {}
+        }
+    }
+
+    for (i, svc) in dev.services.iter().enumerate() {
+        let idx = i.to_string();
+        let at = |key: &'static str| ["devenv", "services", idx.as_str(), key].map(String::from);
+        let mut problem = |key: &'static str, message: String| {
+            problems.push((at(key).to_vec(), message, None));
+        };
+        if !valid_name(&svc.name) {
+            problem(
+                "name",
+                format!("`{}` is not a valid service name", svc.name),
+            );
+        } else if dev.services[..i].iter().any(|s| s.name == svc.name) {
+            problem("name", format!("service `{}` is defined twice", svc.name));
+        }
+        if let Err(e) = check_image(&svc.image) {
+            problem(
+                "image",
+                format!("`{}` is not a valid image reference: {e}", svc.image),
+            );
+        }
+        for port in &svc.ports {
+            if let Err(e) = parse_port(port) {
+                problem("ports", format!("invalid port `{port}`: {e}"));
+            }
+        }
+        for volume in &svc.volumes {
+            if !volume.contains(':') {
+                problem(
+                    "volumes",
+                    format!("volume `{volume}` must be SOURCE:TARGET"),
+                );
+            }
+        }
+        for dep in &svc.depends_on {
+            if !dev.services[..i].iter().any(|s| &s.name == dep) {
+                problem(
+                    "depends_on",
+                    format!("`{dep}` must be a service listed before `{}`", svc.name),
+                );
+            }
+        }
+        if let Some(hc) = &svc.healthcheck {
+            for value in [&hc.interval, &hc.timeout, &hc.start_period]
+                .into_iter()
+                .flatten()
+            {
+                if let Err(e) = parse_duration(value) {
+                    problem("healthcheck", e.to_string());
+                }
+            }
+        }
+    }
+    problems
+}
+
+/// Names Docker accepts in `--ulimit`
+const ULIMITS: &[&str] = &[
+    "core",
+    "cpu",
+    "data",
+    "fsize",
+    "locks",
+    "memlock",
+    "msgqueue",
+    "nice",
+    "nofile",
+    "nproc",
+    "rss",
+    "rtprio",
+    "rttime",
+    "sigpending",
+    "stack",
+];
+
+fn check_resources(res: &Resources, problem: &mut impl FnMut(&[&str], String, Option<&str>)) {
+    let at = |key: &'static str| ["devenv", "resources", key];
+    if let Some(cpus) = res.cpus
+        && cpus <= 0.0
+    {
+        problem(&at("cpus"), "`cpus` must be positive".into(), None);
+    }
+
+    let sizes = [
+        ("memory", &res.memory),
+        ("memory_swap", &res.memory_swap),
+        ("shm_size", &res.shm_size),
+    ]
+    .map(|(key, value)| match value.as_deref() {
+        Some("-1") if key == "memory_swap" => None,
+        None => None,
+        Some(value) => Some((key, parse_size(value))),
+    });
+    for (key, result) in sizes.iter().flatten() {
+        if let Err(e) = result {
+            problem(&at(key), e.to_string(), None);
+        }
+    }
+    let [memory, swap, _] = sizes.map(|s| s.and_then(|(_, r)| r.ok()));
+    if let Some(memory) = memory
+        && memory < MIN_MEMORY
+    {
+        problem(&at("memory"), "`memory` must be at least 6m".into(), None);
+    }
+    if res.memory_swap.is_some() && res.memory.is_none() {
+        problem(
+            &at("memory_swap"),
+            "`memory_swap` needs `memory`".into(),
+            None,
+        );
+    } else if let (Some(memory), Some(swap)) = (memory, swap)
+        && swap < memory
+    {
+        problem(
+            &at("memory_swap"),
+            "`memory_swap` must be at least `memory`".into(),
+            Some("it limits memory plus swap; use -1 for unlimited swap"),
+        );
+    }
+
+    if res.pids_limit == Some(0) {
+        problem(
+            &at("pids_limit"),
+            "`pids_limit` must not be 0".into(),
+            Some("use -1 for no limit"),
+        );
+    }
+    for (name, limit) in &res.ulimits {
+        let path = ["devenv", "resources", "ulimits", name.as_str()];
+        let (soft, hard) = limit.soft_hard();
+        if !ULIMITS.contains(&name.as_str()) {
+            problem(&path, format!("unknown ulimit `{name}`"), None);
+        } else if soft > hard {
+            problem(
+                &path,
+                format!("soft limit {soft} is above the hard limit {hard}"),
+                None,
+            );
+        }
+    }
+}
+
+// Container and network names are derived from it, so stick to Docker's rules
+fn valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+/// Check an image reference: `[REGISTRY[:PORT]/]PATH[:TAG][@DIGEST]`
+pub fn check_image(image: &str) -> Result<(), String> {
+    let (name, digest) = match image.split_once('@') {
+        Some((name, digest)) => (name, Some(digest)),
+        None => (image, None),
+    };
+    if let Some(digest) = digest {
+        let (algo, hex) = digest
+            .split_once(':')
+            .ok_or("digest must be ALGORITHM:HEX")?;
+        if algo.is_empty() || hex.len() < 32 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid digest `{digest}`"));
+        }
+    }
+    let (name, tag) = match name.rsplit_once(':') {
+        Some((n, t)) if !t.contains('/') => (n, Some(t)),
+        _ => (name, None),
+    };
+    if let Some(tag) = tag {
+        let valid = tag.len() <= 128
+            && tag.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+        if !valid {
+            return Err(format!("invalid tag `{tag}`"));
+        }
+    }
+    let mut parts: Vec<&str> = name.split('/').collect();
+    // A first part with `.` or `:`, or `localhost`, is a registry host
+    if parts.len() > 1 && (parts[0].contains(['.', ':']) || parts[0] == "localhost") {
+        let host = parts.remove(0);
+        let (host, port) = host.split_once(':').unwrap_or((host, "80"));
+        if host.is_empty() || port.parse::<u16>().is_err() {
+            return Err(format!("invalid registry `{host}`"));
+        }
+    }
+    for part in parts {
+        let valid = !part.is_empty()
+            && part.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+            && part.ends_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+            && part
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-".contains(c));
+        if !valid {
+            return Err(format!(
+                "invalid repository name `{name}` (lowercase letters, digits and `._-` only)"
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Span of the value at `path`; numeric parts index into arrays
+fn find_span(table: &DeTable, path: &[&str]) -> Option<Range<usize>> {
+    let (first, rest) = path.split_first()?;
+    let value = table.get(*first)?;
+    descend(value, rest)
+}
+
+fn descend(value: &Spanned<DeValue>, path: &[&str]) -> Option<Range<usize>> {
+    let Some((first, rest)) = path.split_first() else {
+        return Some(value.span());
+    };
+    match value.get_ref() {
+        DeValue::Table(t) => descend(t.get(*first)?, rest),
+        DeValue::Array(a) => descend(a.get(first.parse::<usize>().ok()?)?, rest),
+        _ => Some(value.span()),
+    }
+}
+
+// Span of the first `key`, at any depth
+fn find_key(table: &DeTable, key: &str) -> Option<Range<usize>> {
+    fn in_value(value: &Spanned<DeValue>, key: &str) -> Option<Range<usize>> {
+        match value.get_ref() {
+            DeValue::Table(t) => find_key(t, key),
+            DeValue::Array(a) => a.iter().find_map(|v| in_value(v, key)),
+            _ => None,
+        }
+    }
+    table.iter().find_map(|(k, v)| {
+        if k.get_ref() == key {
+            Some(k.span())
+        } else {
+            in_value(v, key)
+        }
+    })
+}
+
+fn did_you_mean<'a>(unknown: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    candidates
+        .iter()
+        .map(|c| (edit_distance(unknown, c), *c))
+        .filter(|(d, c)| *d <= (c.len() / 3).max(1))
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (diag + usize::from(ca != *cb))
+                .min(row[j] + 1)
+                .min(row[j + 1] + 1);
+            diag = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+// error: message
+//   --> devenv.toml:5:1
+//    |
+//  5 | pakages = ["git"]
+//    | ^^^^^^^
+//    = help: did you mean `packages`?
+fn render(name: &str, source: &str, d: &Diagnostic) -> String {
+    let mut out = format!("error: {}\n", d.message);
+    if let Some(span) = &d.span {
+        let start = span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line_no = source[..start].matches('\n').count() + 1;
+        let col = source[line_start..start].chars().count();
+        let width = source[start..span.end.clamp(start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+        let gutter = " ".repeat(line_no.to_string().len());
+        let _ = writeln!(out, "{gutter}--> {name}:{line_no}:{}", col + 1);
+        let _ = writeln!(out, "{gutter} |");
+        let _ = writeln!(out, "{line_no} | {}", &source[line_start..line_end]);
+        let _ = writeln!(out, "{gutter} | {}{}", " ".repeat(col), "^".repeat(width));
+        if let Some(help) = &d.help {
+            let _ = writeln!(out, "{gutter} = help: {help}");
+        }
+    } else {
+        let _ = writeln!(out, " --> {name}");
+        if let Some(help) = &d.help {
+            let _ = writeln!(out, "  = help: {help}");
+        }
+    }
+    out
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const BASE: &str = r#"[devenv]
+name = "app"
+image = "rust:1.82-trixie"
+packages = []
+commands = []
+provision_as_non_root = false
+"#;
+
+    fn check(source: &str, is_root: bool) -> Vec<Diagnostic> {
+        let path = PathBuf::from("devenv.toml");
+        let layers = layers::from_source(&path, source.to_string());
+        report(path, layers, is_root).unwrap().diagnostics
+    }
+
+    fn messages(source: &str) -> Vec<String> {
+        check(source, false)
+            .into_iter()
+            .map(|d| d.message)
+            .collect()
+    }
+
+    // Messages with the source text each diagnostic points at
+    fn located(source: &str) -> Vec<(String, String)> {
+        check(source, false)
+            .into_iter()
+            .map(|d| {
+                let span = source[d.span.unwrap()].to_string();
+                (d.message, span)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn suggests_known_keys_with_spans() {
+        let source = BASE.replace("packages", "pakages");
+        let diags = check(&source, false);
+        assert_eq!(diags.len(), 1);
+        let d = &diags[0];
+        assert_eq!(d.message, "unknown field `pakages`");
+        assert_eq!(d.help.as_deref(), Some("did you mean `packages`?"));
+        assert_eq!(&source[d.span.clone().unwrap()], "pakages");
+
+        let report = Report {
+            path: PathBuf::from("devenv.toml"),
+            files: vec![(PathBuf::from("devenv.toml"), source)],
+            diagnostics: diags,
+        };
+        assert_eq!(
+            report.render(),
+            "error: unknown field `pakages`\n --> devenv.toml:4:1\n  |\n4 | pakages = []\n  | ^^^^^^^\n  = help: did you mean `packages`?\n"
+        );
+    }
+
+    #[test]
+    fn reports_syntax_errors_at_their_location() {
+        let diags = check("[devenv]\nname = \"a\nimage = 1\n", false);
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].span.is_some());
+    }
+
+    #[test]
+    fn checks_values() {
+        assert!(messages(BASE).is_empty());
+
+        let source = BASE
+            .replace("\"app\"", "\"\"")
+            .replace("rust:1.82-trixie", "Rust:latest")
+            + "user_name = \"dev\"\nuser_uid = 0\n\n[devenv.zed_remote]\nenabled = true\nssh_port = 22\n";
+        assert_eq!(
+            messages(&source),
+            vec![
+                "`name` must not be empty",
+                "`Rust:latest` is not a valid image reference: invalid repository name `Rust` (lowercase letters, digits and `._-` only)",
+                "`user_uid` must not be 0",
+                "`ssh_port` 22 is a privileged port",
+            ]
+        );
+        let diags = check(&source, false);
+        assert_eq!(&source[diags[3].span.clone().unwrap()], "22");
+        // Root may publish privileged ports
+        assert_eq!(check(&source, true).len(), 3);
+    }
+
+    #[test]
+    fn checks_env() {
+        let source = BASE.to_string()
+            + "env = { RUST_LOG = \"debug\", \"MY VAR\" = \"1\", \"A=B\" = \"2\" }\n";
+        assert_eq!(
+            located(&source),
+            [
+                ("`A=B` is not a valid environment variable name", "\"2\""),
+                ("`MY VAR` is not a valid environment variable name", "\"1\""),
+            ]
+            .map(|(message, span)| (message.to_string(), span.to_string()))
+        );
+    }
+
+    #[test]
+    fn checks_environment_names() {
+        for name in ["web-1", "my_app.dev"] {
+            assert!(messages(&BASE.replace("\"app\"", &format!("{name:?}"))).is_empty());
+        }
+        for name in ["MyApp", "-app", "my app"] {
+            let diags = check(&BASE.replace("\"app\"", &format!("{name:?}")), false);
+            assert_eq!(diags.len(), 1, "{name}");
+            assert_eq!(
+                diags[0].message,
+                format!("`{name}` is not a valid environment name")
+            );
+        }
+    }
+
+    #[test]
+    fn checks_ports() {
+        let source = BASE.to_string()
+            + r#"ports = ["8080:80", "127.0.0.1:8080:80", "8080:8080", "80", "443:443", "2222:22"]
+
+[devenv.zed_remote]
+enabled = true
+"#;
+        assert_eq!(
+            located(&source),
+            [
+                ("host port 8080 is published twice", "\"8080:8080\""),
+                ("host port 443 is a privileged port", "\"443:443\""),
+                ("host port 2222 is the Zed remote `ssh_port`", "\"2222:22\""),
+            ]
+            .map(|(message, span)| (message.to_string(), span.to_string()))
+        );
+        assert_eq!(check(&source, true).len(), 2);
+
+        // Malformed ports fail to parse, pointing at the entry
+        let diags = check(
+            &BASE.replace("packages", "ports = [\"80:http\"]\npackages"),
+            false,
+        );
+        assert_eq!(
+            diags[0].message,
+            "invalid port `80:http`: 'http' is not a port number"
+        );
+    }
+
+    #[test]
+    fn checks_resources() {
+        let source = BASE.to_string()
+            + r#"
+[devenv.resources]
+cpus = 0
+memory = "4mb"
+memory_swap = "2m"
+pids_limit = 0
+shm_size = "lots"
+ulimits = { nofile = { soft = 2048, hard = 1024 }, files = 10 }
+"#;
+        assert_eq!(
+            messages(&source),
+            vec![
+                "`cpus` must be positive",
+                "'lots' is not a size",
+                "`memory` must be at least 6m",
+                "`memory_swap` must be at least `memory`",
+                "`pids_limit` must not be 0",
+                "unknown ulimit `files`",
+                "soft limit 2048 is above the hard limit 1024",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_mounts() {
+        let source = BASE.to_string()
+            + r#"
+[[devenv.mounts]]
+target = "cache"
+
+[[devenv.mounts]]
+type = "volume"
+source = "node modules"
+target = "/workspace/"
+
+[[devenv.mounts]]
+type = "tmpfs"
+source = "x"
+target = "/tmp"
+
+[[devenv.mounts]]
+type = "tmpfs"
+target = "/tmp/"
+"#;
+        assert_eq!(
+            located(&source),
+            [
+                ("mount target `cache` must be an absolute path", "\"cache\""),
+                ("a bind mount needs a `source`", "\"cache\""),
+                ("`/workspace` is the project mount", "\"/workspace/\""),
+                (
+                    "`node modules` is not a valid volume name",
+                    "\"node modules\""
+                ),
+                ("a tmpfs mount has no `source`", "\"x\""),
+                ("`/tmp/` is mounted twice", "\"/tmp/\""),
+            ]
+            .map(|(message, span)| (message.to_string(), span.to_string()))
+        );
+    }
+
+    #[test]
+    fn checks_services() {
+        let source = BASE.to_string()
+            + r#"
+[[devenv.services]]
+name = "db"
+image = "postgres:16"
+ports = ["5432:pg"]
+
+[[devenv.services]]
+name = "api"
+image = "example/api"
+depends_on = ["cache"]
+healthcheck = { test = "true", interval = "5" }
+"#;
+        assert_eq!(
+            located(&source),
+            [
+                (
+                    "invalid port `5432:pg`: 'pg' is not a port number",
+                    "[\"5432:pg\"]"
+                ),
+                (
+                    "`cache` must be a service listed before `api`",
+                    "[\"cache\"]"
+                ),
+                (
+                    "'5' is missing a unit (h, m, s or ms)",
+                    "{ test = \"true\", interval = \"5\" }"
+                ),
+            ]
+            .map(|(message, span)| (message.to_string(), span.to_string()))
+        );
+    }
+
+    #[test]
+    fn points_into_the_file_a_value_came_from() {
+        let td = TempDir::new().unwrap();
+        std::fs::write(
+            td.path().join("base.toml"),
+            BASE.replace("packages = []", "packages = []\nports = [\"80:80\"]"),
+        )
+        .unwrap();
+        let project = td.path().join("devenv.toml");
+        std::fs::write(
+            &project,
+            "extends = \"base.toml\"\n\n[devenv]\nports = [\"8080:8080\", \"8080:80\"]\n",
+        )
+        .unwrap();
+        let report = report_for(&project);
+        let found: Vec<_> = report
+            .diagnostics
+            .iter()
+            .map(|d| (d.message.as_str(), d.file, d.span.is_some()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("host port 80 is a privileged port", 0, true),
+                ("host port 8080 is published twice", 1, true),
+            ]
+        );
+        assert!(report.render().contains("base.toml:5:"));
+
+        // Unknown keys are found in the file that has them
+        std::fs::write(
+            &project,
+            "extends = \"base.toml\"\n\n[devenv]\ncomands = []\n",
+        )
+        .unwrap();
+        let report = report_for(&project);
+        assert_eq!(report.diagnostics[0].file, 1);
+        assert!(report.render().contains("devenv.toml:4:1"));
+        let err = Config::open(&project).unwrap_err().to_string();
+        assert!(err.contains("did you mean `commands`?"), "{err}");
+    }
+
+    fn report_for(path: &Path) -> Report {
+        report(path.to_path_buf(), layers::load(path), false).unwrap()
+    }
+
+    #[test]
+    fn validates_image_references() {
+        for ok in [
+            "debian:bookworm-slim",
+            "ghcr.io/org/dev-image:1.0",
+            "localhost:5000/team/app",
+            "mcr.microsoft.com/devcontainers/base@sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef",
+        ] {
+            assert!(check_image(ok).is_ok(), "{ok}");
+        }
+        for bad in ["", "Ubuntu", "debian:", "a//b", "node:-20", "x@sha256:zz"] {
+            assert!(check_image(bad).is_err(), "{bad}");
+        }
+    }
+}
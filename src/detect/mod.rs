@@ -0,0 +1,1175 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::{
+    docker::{file::OsFamily, ignore::glob_match},
+    interpolate::expand_path,
+    registry::devenv_config_dir,
+};
+
+pub use native::NativeDep;
+
+mod native;
+
+/// How many directory levels below the project root detection looks at by default
+pub const DEFAULT_DEPTH: usize = 2;
+
+/// Image used when no rule matches
+pub const DEFAULT_IMAGE: &str = "debian:bookworm-slim";
+
+/// User rules file, under the devenv config directory
+const RULES_FILE: &str = "detect.toml";
+const BUILT_IN: &str = "built-in";
+
+// Dependency, build and VCS directories never hold project roots worth detecting
+const SKIP_DIRS: &[&str] = &[
+    "node_modules",
+    "target",
+    "vendor",
+    "dist",
+    "build",
+    "__pycache__",
+    "venv",
+];
+
+/// A language ecosystem recognised by its project files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ecosystem {
+    Rust,
+    Node,
+    Python,
+    Go,
+    Ruby,
+    Java,
+    Dotnet,
+    Php,
+    Elixir,
+}
+
+/// A detection rule: marker files that identify a stack and the image and
+/// packages to use for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub name: String,
+    /// Ecosystem the rule provides. Rules for the same ecosystem compete, and
+    /// the ecosystem supplies toolchain version pinning and a default image.
+    pub ecosystem: Option<Ecosystem>,
+    /// File names or globs (`*.csproj`) looked for in each directory
+    pub markers: Vec<String>,
+    /// Only match marker files containing this text
+    pub contains: Option<String>,
+    /// Base image when the rule is primary (defaults to the ecosystem's image)
+    pub image: Option<String>,
+    /// Extra OS packages added when the rule matches
+    #[serde(default)]
+    pub packages: Vec<String>,
+    /// Higher priorities win over shallower matches and built-in rules (priority 0)
+    #[serde(default)]
+    pub priority: i32,
+    /// File the rule was loaded from, or `built-in`
+    #[serde(skip_deserializing, default = "built_in")]
+    pub source: String,
+}
+
+// Layout of `detect.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    /// Shared rules file, loaded after the user's own rules
+    team_file: Option<String>,
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+/// Evaluates detection rules against a workspace
+#[derive(Debug, Clone)]
+pub struct Detector {
+    depth: usize,
+    rules: Vec<Rule>,
+}
+
+/// Result of detecting a workspace: every rule evaluated and the environment derived from them
+#[derive(Debug, Clone, Serialize)]
+pub struct Detection {
+    pub root: PathBuf,
+    pub depth: usize,
+    /// Every rule evaluated, in evaluation order
+    pub rules: Vec<RuleResult>,
+    /// Base image for the primary rule, or `DEFAULT_IMAGE`
+    pub image: String,
+    /// OS packages required by the matched rules, secondary toolchains and native dependencies
+    pub packages: Vec<String>,
+    /// Locked dependencies that need native OS packages
+    pub native: Vec<NativeDep>,
+    /// Dockerfile instructions installing secondary toolchains without distro packages
+    pub post_install: Option<String>,
+    /// Secondary toolchains that could not be installed on the image
+    pub warnings: Vec<String>,
+}
+
+/// Outcome of evaluating one rule
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleResult {
+    #[serde(flatten)]
+    pub rule: Rule,
+    /// Matching files relative to the root, shallowest first
+    pub matches: Vec<PathBuf>,
+    /// Toolchain version pinned by the shallowest directory that pins one
+    pub version: Option<String>,
+    /// Part the rule plays in the environment; `None` when nothing matched
+    pub role: Option<Role>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Provides the base image
+    Primary,
+    /// Installed on top of the base image
+    Secondary,
+    /// Matched, but another rule for the same ecosystem took precedence
+    Shadowed,
+}
+
+fn built_in() -> String {
+    BUILT_IN.to_string()
+}
+
+impl Rule {
+    fn built_in(ecosystem: Ecosystem) -> Self {
+        Self {
+            name: ecosystem.name().to_string(),
+            ecosystem: Some(ecosystem),
+            markers: ecosystem.markers().iter().map(|m| m.to_string()).collect(),
+            contains: None,
+            image: None,
+            packages: Vec::new(),
+            priority: 0,
+            source: built_in(),
+        }
+    }
+
+    // Rules compete with others providing the same ecosystem
+    fn group(&self) -> &str {
+        match self.ecosystem {
+            Some(ecosystem) => ecosystem.name(),
+            None => &self.name,
+        }
+    }
+
+    // Marker files in `dir` matching this rule
+    fn matching_files(&self, dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+            .filter_map(|e| e.file_name().into_string().ok())
+            .filter(|name| {
+                self.markers
+                    .iter()
+                    .any(|m| glob_match(m.as_bytes(), name.as_bytes()))
+            })
+            .collect();
+        names.sort();
+        if let Some(needle) = &self.contains {
+            names.retain(|name| {
+                fs::read_to_string(dir.join(name)).is_ok_and(|text| text.contains(needle.as_str()))
+            });
+        }
+        names
+    }
+}
+
+impl Detection {
+    /// The rule that picked the base image, if any matched
+    pub fn primary(&self) -> Option<&RuleResult> {
+        self.rules.iter().find(|r| r.role == Some(Role::Primary))
+    }
+
+    /// Human-readable summary; `explain` adds every rule evaluated and what it matched
+    pub fn render(&self, explain: bool) -> String {
+        let mut out = String::new();
+        if explain {
+            out.push_str(&format!(
+                "Rules evaluated in {} (depth {}):\n",
+                self.root.display(),
+                self.depth
+            ));
+            for result in &self.rules {
+                let rule = &result.rule;
+                let role = match result.role {
+                    Some(Role::Primary) => "primary",
+                    Some(Role::Secondary) => "secondary",
+                    Some(Role::Shadowed) => "shadowed",
+                    None => "-",
+                };
+                let detail = if result.matches.is_empty() {
+                    format!("no match ({})", rule.markers.join(", "))
+                } else {
+                    let files: Vec<_> = result
+                        .matches
+                        .iter()
+                        .map(|m| m.display().to_string())
+                        .collect();
+                    files.join(", ")
+                };
+                out.push_str(&format!("  {:<8} {:<10} {detail}", rule.name, role));
+                if let Some(version) = &result.version {
+                    out.push_str(&format!(" (version {version})"));
+                }
+                if rule.source != BUILT_IN {
+                    out.push_str(&format!(" [{}]", rule.source));
+                }
+                out.push('\n');
+            }
+        }
+
+        if explain && !self.native.is_empty() {
+            out.push_str("Native dependencies:\n");
+            for dep in &self.native {
+                out.push_str(&format!(
+                    "  {}: {} -> {}\n",
+                    dep.lockfile.display(),
+                    dep.dependency,
+                    dep.packages.join(", ")
+                ));
+            }
+        }
+
+        match self.primary() {
+            Some(result) => out.push_str(&format!(
+                "Image: {} (from {})\n",
+                self.image, result.rule.name
+            )),
+            None => out.push_str(&format!("Image: {} (no rule matched)\n", self.image)),
+        }
+        if !self.packages.is_empty() {
+            out.push_str(&format!("Packages: {}\n", self.packages.join(", ")));
+        }
+        if let Some(steps) = &self.post_install {
+            out.push_str("Post-install:\n");
+            for line in steps.lines() {
+                out.push_str(&format!("  {line}\n"));
+            }
+        }
+        for warning in &self.warnings {
+            out.push_str(&format!("Warning: {warning}\n"));
+        }
+        out
+    }
+}
+
+impl Ecosystem {
+    // Order of the built-in rules, which breaks ties when choosing the primary
+    const ALL: [Ecosystem; 9] = [
+        Ecosystem::Rust,
+        Ecosystem::Node,
+        Ecosystem::Python,
+        Ecosystem::Go,
+        Ecosystem::Ruby,
+        Ecosystem::Java,
+        Ecosystem::Dotnet,
+        Ecosystem::Php,
+        Ecosystem::Elixir,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rust => "rust",
+            Self::Node => "node",
+            Self::Python => "python",
+            Self::Go => "go",
+            Self::Ruby => "ruby",
+            Self::Java => "java",
+            Self::Dotnet => "dotnet",
+            Self::Php => "php",
+            Self::Elixir => "elixir",
+        }
+    }
+
+    fn markers(self) -> &'static [&'static str] {
+        match self {
+            Self::Rust => &["Cargo.toml"],
+            Self::Node => &["package.json"],
+            Self::Python => &["pyproject.toml", "requirements.txt"],
+            Self::Go => &["go.mod"],
+            Self::Ruby => &["Gemfile"],
+            Self::Java => &["pom.xml", "build.gradle", "build.gradle.kts"],
+            Self::Dotnet => &["*.csproj"],
+            Self::Php => &["composer.json"],
+            Self::Elixir => &["mix.exs"],
+        }
+    }
+
+    fn version(self, dir: &Path) -> Option<String> {
+        match self {
+            Self::Rust => rust_version(dir),
+            Self::Node => node_version(dir),
+            Self::Python => python_version(dir),
+            Self::Go => go_version(dir),
+            Self::Ruby => ruby_version(dir),
+            Self::Dotnet => dotnet_version(dir),
+            Self::Java | Self::Php | Self::Elixir => None,
+        }
+    }
+
+    /// Base image when this is the primary ecosystem
+    fn image(self, version: Option<String>) -> String {
+        match self {
+            Self::Rust => pinned("rust", version, "trixie", "trixie"),
+            Self::Node => pinned("node", version, "current-bookworm", "bookworm"),
+            Self::Python => pinned("python", version, "bookworm", "bookworm"),
+            Self::Go => pinned("golang", version, "bookworm", "bookworm"),
+            Self::Ruby => pinned("ruby", version, "bookworm", "bookworm"),
+            Self::Java => "eclipse-temurin:latest".to_string(),
+            Self::Dotnet => format!(
+                "mcr.microsoft.com/dotnet/sdk:{}",
+                version.as_deref().unwrap_or("8.0")
+            ),
+            Self::Php => "php:bookworm".to_string(),
+            Self::Elixir => "elixir:slim".to_string(),
+        }
+    }
+
+    /// OS packages installing this toolchain on top of another image; `None`
+    /// when the OS family doesn't package it
+    fn secondary_packages(self, os: OsFamily) -> Option<&'static [&'static str]> {
+        use OsFamily::*;
+        Some(match (self, os) {
+            (Self::Node, _) => &["nodejs", "npm"],
+            (Self::Python, Debian) => &["python3", "python3-pip", "python3-venv"],
+            (Self::Python, Alpine) => &["python3", "py3-pip"],
+            (Self::Python, Rhel) => &["python3", "python3-pip"],
+            (Self::Go, Debian) => &["golang-go"],
+            (Self::Go, Alpine) => &["go"],
+            (Self::Go, Rhel) => &["golang"],
+            (Self::Ruby, Debian) => &["ruby-full"],
+            (Self::Ruby, Alpine) => &["ruby", "ruby-dev"],
+            (Self::Ruby, Rhel) => &["ruby", "ruby-devel"],
+            (Self::Java, Debian) => &["default-jdk-headless", "maven"],
+            (Self::Java, Alpine) => &["openjdk17-jdk", "maven"],
+            (Self::Java, Rhel) => &["java-17-openjdk-devel", "maven"],
+            (Self::Dotnet, Debian) => &["libicu-dev"],
+            (Self::Dotnet, Alpine) => &["icu-libs", "libgcc", "libstdc++"],
+            (Self::Dotnet, Rhel) => &["libicu"],
+            (Self::Php, Debian) => &["php-cli", "composer"],
+            (Self::Php, Alpine) => &["php83", "composer"],
+            (Self::Elixir, Debian | Alpine) => &["elixir"],
+            (Self::Php | Self::Elixir, Rhel) => return None,
+            (Self::Rust, _) => &[],
+        })
+    }
+
+    /// Dockerfile instructions for toolchains that have no (pinnable) distro package
+    fn secondary_install(self, version: Option<&str>) -> Option<String> {
+        match self {
+            Self::Rust => Some(format!(
+                "ENV RUSTUP_HOME=/usr/local/rustup CARGO_HOME=/usr/local/cargo PATH=/usr/local/cargo/bin:$PATH\n\
+                 RUN curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs \\\n    \
+                 | sh -s -- -y --no-modify-path --profile minimal --default-toolchain {} \\\n    \
+                 && chmod -R a+w $RUSTUP_HOME $CARGO_HOME",
+                version.unwrap_or("stable")
+            )),
+            Self::Dotnet => Some(format!(
+                "RUN curl -sSL https://dot.net/v1/dotnet-install.sh \\\n    \
+                 | bash -s -- --channel {} --install-dir /usr/share/dotnet \\\n    \
+                 && ln -s /usr/share/dotnet/dotnet /usr/local/bin/dotnet",
+                version.unwrap_or("8.0")
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl Detector {
+    /// Detector using only the built-in rules
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            rules: Ecosystem::ALL.into_iter().map(Rule::built_in).collect(),
+        }
+    }
+
+    /// Detector using the user's `detect.toml` (and the team file it names)
+    /// ahead of the built-in rules
+    pub fn load(depth: usize) -> Result<Self> {
+        let path = rules_path();
+        let mut rules = Vec::new();
+        if path.exists() {
+            let file = read_rules(&path)?;
+            rules.extend(file.rules);
+            if let Some(team) = file.team_file {
+                let base = path.parent().unwrap_or(Path::new("."));
+                let team = expand_path(&team, base)?;
+                let team = read_rules(&team)?;
+                if team.team_file.is_some() {
+                    bail!("team_file may only be set in {}", path.display());
+                }
+                rules.extend(team.rules);
+            }
+        }
+        Ok(Self::new(depth).with_rules(rules))
+    }
+
+    /// Add rules ahead of the ones already loaded
+    pub fn with_rules(mut self, rules: Vec<Rule>) -> Self {
+        self.rules.splice(0..0, rules);
+        self
+    }
+
+    /// Detect the ecosystems in `root` and up to `depth` directory levels below it,
+    /// choosing a primary base image and installing the rest as secondary toolchains
+    pub fn detect(&self, root: &Path) -> Detection {
+        let mut results: Vec<RuleResult> = self
+            .rules
+            .iter()
+            .map(|rule| RuleResult {
+                rule: rule.clone(),
+                matches: Vec::new(),
+                version: None,
+                role: None,
+            })
+            .collect();
+
+        // Breadth-first, so the shallowest match comes first
+        let dirs = workspace_dirs(root, self.depth);
+        for dir in &dirs {
+            let abs = root.join(dir);
+            for result in &mut results {
+                let matches = result.rule.matching_files(&abs);
+                if matches.is_empty() {
+                    continue;
+                }
+                result
+                    .matches
+                    .extend(matches.into_iter().map(|f| dir.join(f)));
+                if result.version.is_none() {
+                    result.version = result.rule.ecosystem.and_then(|e| e.version(&abs));
+                }
+            }
+        }
+
+        // Higher priority first, then the shallowest match, then rule order
+        let rank = |i: usize| {
+            let r = &results[i];
+            (-r.rule.priority, r.matches[0].components().count(), i)
+        };
+        let mut matched: Vec<usize> = (0..results.len())
+            .filter(|&i| !results[i].matches.is_empty())
+            .collect();
+        matched.sort_by_key(|&i| rank(i));
+
+        let mut image = DEFAULT_IMAGE.to_string();
+        let mut os = OsFamily::from_image(&image);
+        let mut packages: Vec<String> = Vec::new();
+        let mut warnings = Vec::new();
+        let mut steps = Vec::new();
+        let mut groups: Vec<String> = Vec::new();
+        for i in matched {
+            let result = &mut results[i];
+            let rule = &result.rule;
+            if groups.iter().any(|g| g == rule.group()) {
+                result.role = Some(Role::Shadowed);
+                continue;
+            }
+            groups.push(rule.group().to_string());
+
+            if groups.len() == 1 {
+                result.role = Some(Role::Primary);
+                image = match (&rule.image, rule.ecosystem) {
+                    (Some(image), _) => image.clone(),
+                    (None, Some(eco)) => eco.image(result.version.clone()),
+                    (None, None) => DEFAULT_IMAGE.to_string(),
+                };
+                os = OsFamily::from_image(&image);
+            } else {
+                result.role = Some(Role::Secondary);
+                if let Some(eco) = rule.ecosystem {
+                    match eco.secondary_packages(os) {
+                        Some(names) => packages.extend(names.iter().map(|p| p.to_string())),
+                        None => warnings.push(format!(
+                            "no packages install {} on {image}; add them to `packages` or `post_install`",
+                            rule.name
+                        )),
+                    }
+                    steps.extend(eco.secondary_install(result.version.as_deref()));
+                }
+            }
+            packages.extend(rule.packages.iter().cloned());
+        }
+
+        // Native libraries for locked dependencies, named for the image's OS family
+        let native: Vec<NativeDep> = dirs
+            .iter()
+            .flat_map(|dir| native::scan(root, dir, os))
+            .collect();
+        packages.extend(native.iter().flat_map(|n| n.packages.iter().cloned()));
+        let mut seen = std::collections::HashSet::new();
+        packages.retain(|p| seen.insert(p.clone()));
+
+        Detection {
+            root: root.to_path_buf(),
+            depth: self.depth,
+            rules: results,
+            image,
+            packages,
+            native,
+            post_install: (!steps.is_empty()).then(|| steps.join("\n")),
+            warnings,
+        }
+    }
+}
+
+/// `detect.toml` under the devenv config directory. Without `XDG_CONFIG_HOME`,
+/// `~/.config/devenv/detect.toml` is used on every platform when it exists.
+fn rules_path() -> PathBuf {
+    if std::env::var_os("XDG_CONFIG_HOME").is_none()
+        && let Some(home) = dirs::home_dir()
+    {
+        let path = home.join(".config").join("devenv").join(RULES_FILE);
+        if path.exists() {
+            return path;
+        }
+    }
+    devenv_config_dir().join(RULES_FILE)
+}
+
+fn read_rules(path: &Path) -> Result<RulesFile> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+    let mut file: RulesFile =
+        toml::from_str(&contents).with_context(|| format!("Parsing {}", path.display()))?;
+    for rule in &mut file.rules {
+        if rule.markers.is_empty() {
+            bail!("Rule '{}' in {} has no markers", rule.name, path.display());
+        }
+        if rule.image.is_none() && rule.ecosystem.is_none() {
+            bail!(
+                "Rule '{}' in {} needs an image or an ecosystem",
+                rule.name,
+                path.display()
+            );
+        }
+        rule.source = path.display().to_string();
+    }
+    Ok(file)
+}
+
+// Directories to inspect, relative to `root`, shallowest first
+fn workspace_dirs(root: &Path, depth: usize) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = WalkDir::new(root)
+        .max_depth(depth)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name().to_string_lossy();
+            e.depth() == 0 || !(name.starts_with('.') || SKIP_DIRS.contains(&name.as_ref()))
+        })
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_dir())
+        .map(|e| {
+            e.path()
+                .strip_prefix(root)
+                .unwrap_or(e.path())
+                .to_path_buf()
+        })
+        .collect();
+    dirs.sort_by_key(|d| d.components().count());
+    dirs
+}
+
+// Versioned tag (e.g. `rust:1.82-trixie`) when the project pins a version,
+// otherwise the floating tag
+fn pinned(repo: &str, version: Option<String>, floating: &str, variant: &str) -> String {
+    match version {
+        Some(v) => format!("{repo}:{v}-{variant}"),
+        None => format!("{repo}:{floating}"),
+    }
+}
+
+// Only plain numeric versions map onto image tags; channels like `stable`,
+// `nightly` or `lts/*` keep the floating image
+fn numeric_version(v: &str) -> Option<String> {
+    let v = v.trim().trim_start_matches('v');
+    let valid = !v.is_empty()
+        && v.split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()));
+    valid.then(|| v.to_string())
+}
+
+fn read(root: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(root.join(name)).ok()
+}
+
+// First non-comment line of a version file such as `.nvmrc`
+fn first_line(contents: &str) -> Option<&str> {
+    contents
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#'))
+}
+
+fn rust_version(root: &Path) -> Option<String> {
+    if let Some(contents) = read(root, "rust-toolchain.toml") {
+        let doc: toml::Table = toml::from_str(&contents).ok()?;
+        let channel = doc.get("toolchain")?.get("channel")?.as_str()?;
+        return numeric_version(channel);
+    }
+    // Legacy `rust-toolchain` holds either the channel or the same TOML
+    let contents = read(root, "rust-toolchain")?;
+    match toml::from_str::<toml::Table>(&contents) {
+        Ok(doc) => numeric_version(doc.get("toolchain")?.get("channel")?.as_str()?),
+        Err(_) => numeric_version(first_line(&contents)?),
+    }
+}
+
+fn node_version(root: &Path) -> Option<String> {
+    for name in [".nvmrc", ".node-version"] {
+        if let Some(contents) = read(root, name) {
+            return numeric_version(first_line(&contents)?);
+        }
+    }
+    let pkg: serde_json::Value = serde_json::from_str(&read(root, "package.json")?).ok()?;
+    node_range_version(pkg.get("engines")?.get("node")?.as_str()?)
+}
+
+// `engines.node` is a semver range; use the major version of its first
+// bound (`>=18.12 <21` -> `18`) unless it names an exact version
+fn node_range_version(range: &str) -> Option<String> {
+    let range = range.trim();
+    if let Some(exact) = numeric_version(range) {
+        return Some(exact);
+    }
+    let first = range
+        .split(|c: char| c.is_whitespace() || c == '|')
+        .find(|s| !s.is_empty())?
+        .trim_start_matches(['^', '~', '>', '<', '=', 'v']);
+    let major = first.split('.').next()?;
+    numeric_version(major)
+}
+
+fn python_version(root: &Path) -> Option<String> {
+    numeric_version(first_line(&read(root, ".python-version")?)?)
+}
+
+fn go_version(root: &Path) -> Option<String> {
+    let contents = read(root, "go.mod")?;
+    contents
+        .lines()
+        .find_map(|l| l.trim().strip_prefix("go "))
+        .and_then(numeric_version)
+}
+
+fn ruby_version(root: &Path) -> Option<String> {
+    let line = first_line(&read(root, ".ruby-version")?)?.to_string();
+    numeric_version(line.trim_start_matches("ruby-"))
+}
+
+// SDK images are tagged by channel (`8.0`), so drop the feature band
+fn dotnet_version(root: &Path) -> Option<String> {
+    let doc: serde_json::Value = serde_json::from_str(&read(root, "global.json")?).ok()?;
+    let version = numeric_version(doc.get("sdk")?.get("version")?.as_str()?)?;
+    let mut parts = version.split('.');
+    Some(format!("{}.{}", parts.next()?, parts.next()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn detect(root: &Path, depth: usize) -> Detection {
+        Detector::new(depth).detect(root)
+    }
+
+    fn detect_base_image(root: &Path) -> Option<String> {
+        let detection = detect(root, DEFAULT_DEPTH);
+        detection.primary().is_some().then_some(detection.image)
+    }
+
+    #[test]
+    fn detects_rust() {
+        let td = TempDir::new().unwrap();
+        fs::write(td.path().join("Cargo.toml"), "[package]\nname='x'\n").unwrap();
+        let img = detect_base_image(td.path());
+        assert_eq!(img.as_deref(), Some("rust:trixie"));
+    }
+
+    #[test]
+    fn detects_node() {
+        let td = TempDir::new().unwrap();
+        fs::write(td.path().join("package.json"), "{}\n").unwrap();
+        assert_eq!(
+            detect_base_image(td.path()).as_deref(),
+            Some("node:current-bookworm")
+        );
+    }
+
+    #[test]
+    fn detects_python() {
+        let td = TempDir::new().unwrap();
+        fs::write(td.path().join("requirements.txt"), "requests\n").unwrap();
+        assert_eq!(
+            detect_base_image(td.path()).as_deref(),
+            Some("python:bookworm")
+        );
+    }
+
+    #[test]
+    fn detects_java_gradle() {
+        let td = TempDir::new().unwrap();
+        fs::write(td.path().join("build.gradle"), "plugins {}\n").unwrap();
+        assert_eq!(
+            detect_base_image(td.path()).as_deref(),
+            Some("eclipse-temurin:latest")
+        );
+    }
+
+    #[test]
+    fn detects_dotnet_csproj() {
+        let td = TempDir::new().unwrap();
+        let sub = td.path().join("src");
+        std::fs::create_dir_all(&sub).unwrap();
+        fs::write(sub.join("app.csproj"), "<Project/>\n").unwrap();
+        assert_eq!(
+            detect_base_image(td.path()).as_deref(),
+            Some("mcr.microsoft.com/dotnet/sdk:8.0")
+        );
+    }
+
+    #[test]
+    fn pins_rust_toolchain() {
+        let td = TempDir::new().unwrap();
+        fs::write(td.path().join("Cargo.toml"), "[package]\nname='x'\n").unwrap();
+        fs::write(
+            td.path().join("rust-toolchain.toml"),
+            "[toolchain]\nchannel = \"1.82\"\ncomponents = [\"clippy\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            detect_base_image(td.path()).as_deref(),
+            Some("rust:1.82-trixie")
+        );
+
+        // Named channels keep the floating image
+        fs::remove_file(td.path().join("rust-toolchain.toml")).unwrap();
+        fs::write(td.path().join("rust-toolchain"), "stable\n").unwrap();
+        assert_eq!(detect_base_image(td.path()).as_deref(), Some("rust:trixie"));
+        fs::write(td.path().join("rust-toolchain"), "1.80.1\n").unwrap();
+        assert_eq!(
+            detect_base_image(td.path()).as_deref(),
+            Some("rust:1.80.1-trixie")
+        );
+    }
+
+    #[test]
+    fn pins_node_from_version_files_and_engines() {
+        let td = TempDir::new().unwrap();
+        fs::write(
+            td.path().join("package.json"),
+            r#"{"engines": {"node": ">=18.12 <21"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            detect_base_image(td.path()).as_deref(),
+            Some("node:18-bookworm")
+        );
+        fs::write(td.path().join(".node-version"), "20.11.1\n").unwrap();
+        assert_eq!(
+            detect_base_image(td.path()).as_deref(),
+            Some("node:20.11.1-bookworm")
+        );
+        fs::write(td.path().join(".nvmrc"), "v22\n").unwrap();
+        assert_eq!(
+            detect_base_image(td.path()).as_deref(),
+            Some("node:22-bookworm")
+        );
+        fs::write(td.path().join(".nvmrc"), "lts/iron\n").unwrap();
+        assert_eq!(
+            detect_base_image(td.path()).as_deref(),
+            Some("node:current-bookworm")
+        );
+
+        assert_eq!(node_range_version("^20.10.0").as_deref(), Some("20"));
+        assert_eq!(node_range_version("18 || 20").as_deref(), Some("18"));
+        assert_eq!(node_range_version("*"), None);
+    }
+
+    #[test]
+    fn pins_python_go_ruby_and_dotnet() {
+        let td = TempDir::new().unwrap();
+        let root = td.path();
+        fs::write(root.join("requirements.txt"), "requests\n").unwrap();
+        fs::write(root.join(".python-version"), "3.12\n").unwrap();
+        assert_eq!(
+            detect_base_image(root).as_deref(),
+            Some("python:3.12-bookworm")
+        );
+
+        let td = TempDir::new().unwrap();
+        let root = td.path();
+        fs::write(
+            root.join("go.mod"),
+            "module x\n\ngo 1.22.1\n\ntoolchain go1.23.0\n",
+        )
+        .unwrap();
+        assert_eq!(
+            detect_base_image(root).as_deref(),
+            Some("golang:1.22.1-bookworm")
+        );
+
+        let td = TempDir::new().unwrap();
+        let root = td.path();
+        fs::write(root.join("Gemfile"), "source 'https://rubygems.org'\n").unwrap();
+        fs::write(root.join(".ruby-version"), "ruby-3.3.0\n").unwrap();
+        assert_eq!(
+            detect_base_image(root).as_deref(),
+            Some("ruby:3.3.0-bookworm")
+        );
+
+        let td = TempDir::new().unwrap();
+        let root = td.path();
+        fs::write(root.join("app.csproj"), "<Project/>\n").unwrap();
+        fs::write(
+            root.join("global.json"),
+            r#"{"sdk": {"version": "9.0.100", "rollForward": "latestFeature"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            detect_base_image(root).as_deref(),
+            Some("mcr.microsoft.com/dotnet/sdk:9.0")
+        );
+    }
+
+    #[test]
+    fn detects_monorepo_with_secondary_toolchains() {
+        let td = TempDir::new().unwrap();
+        let root = td.path();
+        fs::create_dir_all(root.join("server")).unwrap();
+        fs::create_dir_all(root.join("web/node_modules/dep")).unwrap();
+        fs::write(root.join("server/Cargo.toml"), "[package]\nname='x'\n").unwrap();
+        fs::write(root.join("web/package.json"), "{}\n").unwrap();
+        fs::write(root.join("web/.nvmrc"), "20\n").unwrap();
+        // Dependencies are not part of the workspace
+        fs::write(root.join("web/node_modules/dep/pyproject.toml"), "").unwrap();
+
+        let env = detect(root, DEFAULT_DEPTH);
+        assert_eq!(env.image, "rust:trixie");
+        assert_eq!(env.packages, vec!["nodejs", "npm"]);
+        assert_eq!(env.post_install, None);
+        let found: Vec<_> = env
+            .rules
+            .iter()
+            .filter_map(|r| r.role.map(|role| (r.rule.ecosystem, role)))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (Some(Ecosystem::Rust), Role::Primary),
+                (Some(Ecosystem::Node), Role::Secondary)
+            ]
+        );
+        let node = &env.rules[1];
+        assert_eq!(node.matches, vec![PathBuf::from("web/package.json")]);
+        assert_eq!(node.version.as_deref(), Some("20"));
+
+        // A project file at the root wins over deeper ones
+        fs::write(root.join("pyproject.toml"), "[project]\nname='x'\n").unwrap();
+        fs::write(root.join(".python-version"), "3.12\n").unwrap();
+        let env = detect(root, DEFAULT_DEPTH);
+        assert_eq!(env.image, "python:3.12-bookworm");
+        assert_eq!(env.packages, vec!["nodejs", "npm"]);
+        let rustup = env.post_install.unwrap();
+        assert!(rustup.contains("https://sh.rustup.rs"));
+        assert!(rustup.contains("--default-toolchain stable"));
+
+        // Depth 0 only looks at the root
+        let env = detect(root, 0);
+        assert_eq!(env.rules.iter().filter(|r| r.role.is_some()).count(), 1);
+        assert!(env.packages.is_empty());
+    }
+
+    #[test]
+    fn explains_rules_and_serializes() {
+        let td = TempDir::new().unwrap();
+        let root = td.path();
+        fs::create_dir_all(root.join("web")).unwrap();
+        fs::write(root.join("Cargo.toml"), "[package]\nname='x'\n").unwrap();
+        fs::write(root.join("rust-toolchain"), "1.82\n").unwrap();
+        fs::write(root.join("web/package.json"), "{}\n").unwrap();
+
+        let env = detect(root, DEFAULT_DEPTH);
+        let text = env.render(true);
+        assert!(text.contains("  rust     primary    Cargo.toml (version 1.82)\n"));
+        assert!(text.contains("  node     secondary  web/package.json\n"));
+        assert!(
+            text.contains("  python   -          no match (pyproject.toml, requirements.txt)\n")
+        );
+        assert!(text.ends_with("Image: rust:1.82-trixie (from rust)\nPackages: nodejs, npm\n"));
+        assert!(!env.render(false).contains("Rules evaluated"));
+
+        let json = serde_json::to_value(&env).unwrap();
+        assert_eq!(json["image"], "rust:1.82-trixie");
+        assert_eq!(json["rules"][0]["ecosystem"], "rust");
+        assert_eq!(json["rules"][0]["role"], "primary");
+        assert_eq!(json["rules"][2]["role"], serde_json::Value::Null);
+        assert_eq!(json["rules"][6]["markers"][0], "*.csproj");
+    }
+
+    fn rules(toml: &str) -> Vec<Rule> {
+        toml::from_str::<RulesFile>(toml).unwrap().rules
+    }
+
+    #[test]
+    fn custom_rules_take_precedence_and_shadow_builtins() {
+        let td = TempDir::new().unwrap();
+        let root = td.path();
+        fs::create_dir_all(root.join("web")).unwrap();
+        fs::write(
+            root.join("Cargo.toml"),
+            "[dependencies]\ntonic = \"0.12\"\n",
+        )
+        .unwrap();
+        fs::write(root.join("web/package.json"), "{}\n").unwrap();
+        fs::write(root.join("schema.proto"), "syntax = \"proto3\";\n").unwrap();
+
+        let custom = rules(
+            r#"
+[[rules]]
+name = "grpc-rust"
+ecosystem = "rust"
+markers = ["Cargo.toml"]
+contains = "tonic"
+image = "registry.local/rust-dev:2025"
+packages = ["protobuf-compiler"]
+
+[[rules]]
+name = "protos"
+markers = ["*.proto"]
+image = "registry.local/buf:1"
+packages = ["protobuf-compiler", "buf"]
+"#,
+        );
+        let env = Detector::new(DEFAULT_DEPTH).with_rules(custom).detect(root);
+        assert_eq!(env.image, "registry.local/rust-dev:2025");
+        assert_eq!(
+            env.packages,
+            vec!["protobuf-compiler", "buf", "nodejs", "npm"]
+        );
+        let roles: Vec<_> = env
+            .rules
+            .iter()
+            .map(|r| (r.rule.name.as_str(), r.role))
+            .take(4)
+            .collect();
+        assert_eq!(
+            roles,
+            vec![
+                ("grpc-rust", Some(Role::Primary)),
+                ("protos", Some(Role::Secondary)),
+                ("rust", Some(Role::Shadowed)),
+                ("node", Some(Role::Secondary)),
+            ]
+        );
+        assert_eq!(env.post_install, None);
+
+        // Content matches gate the rule; priority beats depth
+        fs::write(root.join("Cargo.toml"), "[dependencies]\n").unwrap();
+        let custom = rules(
+            r#"
+[[rules]]
+name = "grpc-rust"
+ecosystem = "rust"
+markers = ["Cargo.toml"]
+contains = "tonic"
+image = "registry.local/rust-dev:2025"
+
+[[rules]]
+name = "frontend"
+ecosystem = "node"
+markers = ["package.json"]
+image = "registry.local/node-dev:22"
+priority = 10
+"#,
+        );
+        let env = Detector::new(DEFAULT_DEPTH).with_rules(custom).detect(root);
+        assert_eq!(env.rules[0].role, None);
+        assert_eq!(env.image, "registry.local/node-dev:22");
+        assert!(env.post_install.unwrap().contains("sh.rustup.rs"));
+    }
+
+    #[test]
+    fn names_secondary_packages_for_the_image_os() {
+        let td = TempDir::new().unwrap();
+        let root = td.path();
+        fs::create_dir_all(root.join("api")).unwrap();
+        fs::write(root.join("package.json"), "{}\n").unwrap();
+        fs::write(root.join("api/requirements.txt"), "flask\n").unwrap();
+
+        let custom = rules(
+            "[[rules]]\nname = \"web\"\necosystem = \"node\"\nmarkers = [\"package.json\"]\nimage = \"node:20-alpine\"\n",
+        );
+        let env = Detector::new(DEFAULT_DEPTH).with_rules(custom).detect(root);
+        assert_eq!(env.image, "node:20-alpine");
+        assert_eq!(env.packages, vec!["python3", "py3-pip"]);
+        assert!(env.warnings.is_empty());
+
+        // Toolchains the OS family doesn't package are skipped with a warning
+        fs::write(root.join("api/composer.json"), "{}\n").unwrap();
+        let custom = rules(
+            "[[rules]]\nname = \"web\"\necosystem = \"node\"\nmarkers = [\"package.json\"]\nimage = \"fedora:40\"\n",
+        );
+        let env = Detector::new(DEFAULT_DEPTH).with_rules(custom).detect(root);
+        assert_eq!(env.packages, vec!["python3", "python3-pip"]);
+        assert_eq!(
+            env.warnings,
+            vec!["no packages install php on fedora:40; add them to `packages` or `post_install`"]
+        );
+        assert!(
+            env.render(false)
+                .ends_with(&format!("Warning: {}\n", env.warnings[0]))
+        );
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn loads_user_and_team_rules() {
+        let td = TempDir::new().unwrap();
+        let cfg_dir = td.path().join("devenv");
+        fs::create_dir_all(&cfg_dir).unwrap();
+        fs::write(
+            cfg_dir.join(RULES_FILE),
+            "team_file = \"team.toml\"\n\n[[rules]]\nname = \"mine\"\nmarkers = [\"Makefile\"]\nimage = \"me:1\"\n",
+        )
+        .unwrap();
+        fs::write(
+            cfg_dir.join("team.toml"),
+            "[[rules]]\nname = \"team\"\necosystem = \"go\"\nmarkers = [\"go.mod\"]\npriority = 5\n",
+        )
+        .unwrap();
+
+        let _config_home = EnvGuard::set("XDG_CONFIG_HOME", Some(td.path()));
+        let detector = Detector::load(DEFAULT_DEPTH).unwrap();
+        let names: Vec<_> = detector
+            .rules
+            .iter()
+            .map(|r| r.name.as_str())
+            .take(3)
+            .collect();
+        assert_eq!(names, vec!["mine", "team", "rust"]);
+        assert!(detector.rules[1].source.ends_with("team.toml"));
+        assert_eq!(detector.rules[2].source, BUILT_IN);
+
+        fs::write(
+            cfg_dir.join(RULES_FILE),
+            "[[rules]]\nname = \"bad\"\nmarkers = [\"x\"]\n",
+        )
+        .unwrap();
+        let err = Detector::load(DEFAULT_DEPTH).unwrap_err();
+        assert!(err.to_string().contains("needs an image or an ecosystem"));
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn loads_rules_from_dot_config_without_xdg_config_home() {
+        let td = TempDir::new().unwrap();
+        let cfg_dir = td.path().join(".config/devenv");
+        fs::create_dir_all(&cfg_dir).unwrap();
+        fs::write(
+            cfg_dir.join(RULES_FILE),
+            "[[rules]]\nname = \"mine\"\nmarkers = [\"Makefile\"]\nimage = \"me:1\"\n",
+        )
+        .unwrap();
+
+        let _config_home = EnvGuard::set("XDG_CONFIG_HOME", None);
+        let _home = EnvGuard::set("HOME", Some(td.path()));
+        let detector = Detector::load(DEFAULT_DEPTH).unwrap();
+        assert_eq!(detector.rules[0].name, "mine");
+    }
+
+    // Sets an environment variable, restoring the old value when dropped
+    struct EnvGuard(&'static str, Option<std::ffi::OsString>);
+
+    impl EnvGuard {
+        fn set(name: &'static str, value: Option<&Path>) -> Self {
+            let old = std::env::var_os(name);
+            unsafe {
+                match value {
+                    Some(value) => std::env::set_var(name, value),
+                    None => std::env::remove_var(name),
+                }
+            }
+            Self(name, old)
+        }
+    }
+
+    impl Drop for EnvGuard {
+        fn drop(&mut self) {
+            unsafe {
+                match &self.1 {
+                    Some(value) => std::env::set_var(self.0, value),
+                    None => std::env::remove_var(self.0),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn adds_packages_for_native_dependencies() {
+        let td = TempDir::new().unwrap();
+        let root = td.path();
+        fs::write(root.join("Cargo.toml"), "[package]\nname='x'\n").unwrap();
+        fs::write(
+            root.join("Cargo.lock"),
+            "[[package]]\nname = \"openssl-sys\"\n\n[[package]]\nname = \"x\"\n",
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("scripts")).unwrap();
+        fs::write(root.join("scripts/requirements.txt"), "psycopg2==2.9\n").unwrap();
+
+        let env = detect(root, DEFAULT_DEPTH);
+        assert_eq!(env.image, "rust:trixie");
+        assert_eq!(
+            env.packages,
+            vec![
+                "python3",
+                "python3-pip",
+                "python3-venv",
+                "libssl-dev",
+                "pkg-config",
+                "libpq-dev"
+            ]
+        );
+        assert!(env.render(true).contains(
+            "Native dependencies:\n  Cargo.lock: openssl-sys -> libssl-dev, pkg-config\n  scripts/requirements.txt: psycopg2 -> libpq-dev\n"
+        ));
+
+        // Package names follow the OS family of the chosen image
+        let custom = rules(
+            "[[rules]]\nname = \"alpine-rust\"\necosystem = \"rust\"\nmarkers = [\"Cargo.toml\"]\nimage = \"rust:1-alpine\"\n",
+        );
+        let env = Detector::new(0).with_rules(custom).detect(root);
+        assert_eq!(env.packages, vec!["openssl-dev", "pkgconf"]);
+    }
+
+    #[test]
+    fn returns_none_when_unknown() {
+        let td = TempDir::new().unwrap();
+        assert_eq!(detect_base_image(td.path()), None);
+        let env = detect(td.path(), DEFAULT_DEPTH);
+        assert_eq!(env.image, DEFAULT_IMAGE);
+        assert!(
+            env.render(false)
+                .starts_with("Image: debian:bookworm-slim (no rule matched)")
+        );
+    }
+}
@@ -0,0 +1,314 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::docker::file::OsFamily;
+
+/// OS packages providing a native library, per OS family
+struct Native {
+    debian: &'static [&'static str],
+    alpine: &'static [&'static str],
+    rhel: &'static [&'static str],
+}
+
+impl Native {
+    fn packages(&self, os: OsFamily) -> &'static [&'static str] {
+        match os {
+            OsFamily::Debian => self.debian,
+            OsFamily::Alpine => self.alpine,
+            OsFamily::Rhel => self.rhel,
+        }
+    }
+}
+
+const OPENSSL: Native = Native {
+    debian: &["libssl-dev", "pkg-config"],
+    alpine: &["openssl-dev", "pkgconf"],
+    rhel: &["openssl-devel", "pkgconf-pkg-config"],
+};
+const LIBPQ: Native = Native {
+    debian: &["libpq-dev"],
+    alpine: &["postgresql-dev"],
+    rhel: &["libpq-devel"],
+};
+const SQLITE: Native = Native {
+    debian: &["libsqlite3-dev"],
+    alpine: &["sqlite-dev"],
+    rhel: &["sqlite-devel"],
+};
+const MYSQL: Native = Native {
+    debian: &["default-libmysqlclient-dev", "pkg-config"],
+    alpine: &["mariadb-dev", "pkgconf"],
+    rhel: &["mariadb-connector-c-devel", "pkgconf-pkg-config"],
+};
+const ZLIB: Native = Native {
+    debian: &["zlib1g-dev"],
+    alpine: &["zlib-dev"],
+    rhel: &["zlib-devel"],
+};
+const PROTOC: Native = Native {
+    debian: &["protobuf-compiler"],
+    alpine: &["protobuf-dev"],
+    rhel: &["protobuf-compiler"],
+};
+const LIBCLANG: Native = Native {
+    debian: &["libclang-dev"],
+    alpine: &["clang-dev"],
+    rhel: &["clang-devel"],
+};
+const LIBXML: Native = Native {
+    debian: &["libxml2-dev", "libxslt1-dev"],
+    alpine: &["libxml2-dev", "libxslt-dev"],
+    rhel: &["libxml2-devel", "libxslt-devel"],
+};
+const CAIRO: Native = Native {
+    debian: &[
+        "libcairo2-dev",
+        "libpango1.0-dev",
+        "libjpeg-dev",
+        "libgif-dev",
+        "librsvg2-dev",
+    ],
+    alpine: &[
+        "cairo-dev",
+        "pango-dev",
+        "jpeg-dev",
+        "giflib-dev",
+        "librsvg-dev",
+    ],
+    rhel: &[
+        "cairo-devel",
+        "pango-devel",
+        "libjpeg-turbo-devel",
+        "giflib-devel",
+        "librsvg2-devel",
+    ],
+};
+// Compilers for packages that build C extensions from source
+const CC: Native = Native {
+    debian: &["build-essential", "python3"],
+    alpine: &["build-base", "python3"],
+    rhel: &["gcc-c++", "make", "python3"],
+};
+
+/// Lockfile formats that are scanned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lockfile {
+    Cargo,
+    Npm,
+    Pip,
+    Poetry,
+    Bundler,
+}
+
+impl Lockfile {
+    const ALL: [(&'static str, Lockfile); 5] = [
+        ("Cargo.lock", Lockfile::Cargo),
+        ("package-lock.json", Lockfile::Npm),
+        ("requirements.txt", Lockfile::Pip),
+        ("poetry.lock", Lockfile::Poetry),
+        ("Gemfile.lock", Lockfile::Bundler),
+    ];
+
+    /// Dependencies with native requirements, keyed by normalised name
+    fn native_deps(self) -> &'static [(&'static str, &'static Native)] {
+        match self {
+            Self::Cargo => &[
+                ("openssl-sys", &OPENSSL),
+                ("pq-sys", &LIBPQ),
+                ("libsqlite3-sys", &SQLITE),
+                ("mysqlclient-sys", &MYSQL),
+                ("libz-sys", &ZLIB),
+                ("prost-build", &PROTOC),
+                ("tonic-build", &PROTOC),
+                ("clang-sys", &LIBCLANG),
+            ],
+            Self::Npm => &[("canvas", &CAIRO), ("pg-native", &LIBPQ), ("node-gyp", &CC)],
+            Self::Pip | Self::Poetry => &[
+                ("psycopg2", &LIBPQ),
+                ("mysqlclient", &MYSQL),
+                ("lxml", &LIBXML),
+            ],
+            Self::Bundler => &[("pg", &LIBPQ), ("mysql2", &MYSQL), ("sqlite3", &SQLITE)],
+        }
+    }
+
+    fn dependencies(self, contents: &str) -> BTreeSet<String> {
+        let names = match self {
+            Self::Cargo | Self::Poetry => toml_packages(contents),
+            Self::Npm => npm_packages(contents),
+            Self::Pip => pip_requirements(contents),
+            Self::Bundler => bundler_specs(contents),
+        };
+        names.into_iter().map(|n| normalise(&n)).collect()
+    }
+}
+
+/// A locked dependency that needs native OS packages
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NativeDep {
+    /// Lockfile relative to the project root
+    pub lockfile: PathBuf,
+    pub dependency: String,
+    pub packages: Vec<String>,
+}
+
+/// Scan the lockfiles in `dir` for dependencies with known native requirements
+pub fn scan(root: &Path, dir: &Path, os: OsFamily) -> Vec<NativeDep> {
+    let mut found = Vec::new();
+    for (name, lockfile) in Lockfile::ALL {
+        let Ok(contents) = fs::read_to_string(root.join(dir).join(name)) else {
+            continue;
+        };
+        let deps = lockfile.dependencies(&contents);
+        for (dep, native) in lockfile.native_deps() {
+            if deps.contains(*dep) {
+                found.push(NativeDep {
+                    lockfile: dir.join(name),
+                    dependency: dep.to_string(),
+                    packages: native.packages(os).iter().map(|p| p.to_string()).collect(),
+                });
+            }
+        }
+    }
+    found
+}
+
+// Package names compare case-insensitively, with `_` and `-` interchangeable
+fn normalise(name: &str) -> String {
+    name.trim().to_ascii_lowercase().replace('_', "-")
+}
+
+// `[[package]] name = ...` in Cargo.lock and poetry.lock
+fn toml_packages(contents: &str) -> Vec<String> {
+    let Ok(doc) = toml::from_str::<toml::Table>(contents) else {
+        return Vec::new();
+    };
+    doc.get("package")
+        .and_then(|p| p.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|p| p.get("name")?.as_str().map(String::from))
+        .collect()
+}
+
+// Lockfile v2/v3 `packages` keys (`node_modules/a/node_modules/b`) and v1 `dependencies`
+fn npm_packages(contents: &str) -> Vec<String> {
+    let Ok(doc) = serde_json::from_str::<serde_json::Value>(contents) else {
+        return Vec::new();
+    };
+    let mut names = Vec::new();
+    if let Some(packages) = doc.get("packages").and_then(|p| p.as_object()) {
+        names.extend(
+            packages
+                .keys()
+                .filter_map(|k| k.rsplit_once("node_modules/").map(|(_, n)| n.to_string())),
+        );
+    }
+    fn walk(deps: &serde_json::Value, names: &mut Vec<String>) {
+        for (name, dep) in deps.as_object().into_iter().flatten() {
+            names.push(name.clone());
+            if let Some(nested) = dep.get("dependencies") {
+                walk(nested, names);
+            }
+        }
+    }
+    if let Some(deps) = doc.get("dependencies") {
+        walk(deps, &mut names);
+    }
+    names
+}
+
+// Requirement names, without version specifiers, extras or markers
+fn pip_requirements(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(|l| l.split('#').next().unwrap_or_default().trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('-'))
+        .filter_map(|l| {
+            let end = l
+                .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+                .unwrap_or(l.len());
+            (end > 0).then(|| l[..end].to_string())
+        })
+        .collect()
+}
+
+// Gem names under `specs:`, indented by exactly four spaces (`    pg (1.5.4)`)
+fn bundler_specs(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .filter_map(|l| l.strip_prefix("    "))
+        .filter(|l| !l.starts_with(' '))
+        .filter_map(|l| l.split_once(" (").map(|(name, _)| name.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parses_lockfile_formats() {
+        let cargo = "version = 4\n\n[[package]]\nname = \"openssl-sys\"\nversion = \"0.9\"\n\n[[package]]\nname = \"serde\"\n";
+        assert!(Lockfile::Cargo.dependencies(cargo).contains("openssl-sys"));
+
+        let npm = r#"{"packages": {"": {}, "node_modules/canvas": {}, "node_modules/a/node_modules/pg-native": {}},
+                      "dependencies": {"left-pad": {"dependencies": {"node-gyp": {}}}}}"#;
+        let deps = Lockfile::Npm.dependencies(npm);
+        for name in ["canvas", "pg-native", "left-pad", "node-gyp"] {
+            assert!(deps.contains(name), "{name}");
+        }
+
+        let pip =
+            "# db\nPsycopg2==2.9.9 ; python_version > '3'\nrequests[socks]>=2\n-r base.txt\nlxml\n";
+        let deps = Lockfile::Pip.dependencies(pip);
+        assert_eq!(
+            deps.into_iter().collect::<Vec<_>>(),
+            vec!["lxml", "psycopg2", "requests"]
+        );
+
+        let gems = "GEM\n  remote: https://rubygems.org/\n  specs:\n    pg (1.5.4)\n    rails (7.1.0)\n      actionpack (= 7.1.0)\n\nPLATFORMS\n  ruby\n";
+        let deps = Lockfile::Bundler.dependencies(gems);
+        assert_eq!(deps.into_iter().collect::<Vec<_>>(), vec!["pg", "rails"]);
+    }
+
+    #[test]
+    fn maps_dependencies_to_os_packages() {
+        let td = TempDir::new().unwrap();
+        let root = td.path();
+        fs::create_dir_all(root.join("api")).unwrap();
+        fs::write(
+            root.join("api/Cargo.lock"),
+            "[[package]]\nname = \"openssl-sys\"\n\n[[package]]\nname = \"pq-sys\"\n",
+        )
+        .unwrap();
+        fs::write(root.join("api/requirements.txt"), "psycopg2-binary\n").unwrap();
+
+        let found = scan(root, Path::new("api"), OsFamily::Debian);
+        assert_eq!(
+            found,
+            vec![
+                NativeDep {
+                    lockfile: PathBuf::from("api/Cargo.lock"),
+                    dependency: "openssl-sys".into(),
+                    packages: vec!["libssl-dev".into(), "pkg-config".into()],
+                },
+                NativeDep {
+                    lockfile: PathBuf::from("api/Cargo.lock"),
+                    dependency: "pq-sys".into(),
+                    packages: vec!["libpq-dev".into()],
+                },
+            ]
+        );
+
+        let alpine = scan(root, Path::new("api"), OsFamily::Alpine);
+        assert_eq!(alpine[0].packages, vec!["openssl-dev", "pkgconf"]);
+        assert!(scan(root, Path::new(""), OsFamily::Debian).is_empty());
+    }
+}
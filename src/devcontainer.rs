@@ -0,0 +1,951 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    config::{Config, DevEnvConfig, Mount, Port},
+    docker::MountKind,
+    interpolate::substitute,
+    util::{as_string, scalar_string, shell_quote, slash_path},
+};
+
+/// Locations searched for a devcontainer definition, relative to the project
+const CANDIDATES: &[&str] = &[".devcontainer/devcontainer.json", ".devcontainer.json"];
+
+/// Workspace mount point inside devenv containers
+const WORKSPACE: &str = "/workspace";
+
+/// First line of exported files; marks them as safe to overwrite
+const GENERATED: &str = "// Generated by devenv from devenv.toml. Do not edit manually.";
+
+// Subset of the devcontainer.json schema written by `export`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DevContainer {
+    name: String,
+    build: Build,
+    workspace_folder: &'static str,
+    workspace_mount: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remote_user: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    mounts: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    run_args: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    forward_ports: Vec<u16>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    container_env: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_create_command: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Build {
+    dockerfile: String,
+    context: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    args: BTreeMap<String, String>,
+}
+
+/// Find the project's devcontainer.json, if any
+pub fn find(project_dir: &Path) -> Option<PathBuf> {
+    CANDIDATES
+        .iter()
+        .map(|c| project_dir.join(c))
+        .find(|p| p.is_file())
+}
+
+/// Translate the project's devcontainer.json into `devenv`, returning a
+/// warning for everything that could not be represented
+pub fn import(project_dir: &Path, devenv: &mut DevEnvConfig) -> Result<Vec<String>> {
+    let Some(path) = find(project_dir) else {
+        bail!(
+            "No devcontainer.json found in {} (looked for {})",
+            project_dir.display(),
+            CANDIDATES.join(", ")
+        );
+    };
+    let contents =
+        fs::read_to_string(&path).with_context(|| format!("Reading {}", path.display()))?;
+    let json: Value = serde_json::from_str(&strip_jsonc(&contents))
+        .with_context(|| format!("Parsing {}", path.display()))?;
+    let Value::Object(mut json) = json else {
+        bail!("{} must contain a JSON object", path.display());
+    };
+
+    // Paths in devcontainer.json are relative to its own directory
+    let base = path
+        .parent()
+        .and_then(|p| p.strip_prefix(project_dir).ok())
+        .unwrap_or(Path::new(""))
+        .to_path_buf();
+    let mut warnings = Vec::new();
+
+    if let Some(image) = json.remove("image") {
+        devenv.image = as_string("image", &image)?;
+    }
+    if let Some(build) = json.remove("build") {
+        import_build(project_dir, &base, build, devenv, &mut warnings)?;
+    }
+    if devenv.image.is_empty() {
+        bail!(
+            "{} has neither `image` nor `build.dockerfile`",
+            path.display()
+        );
+    }
+    if json.contains_key("dockerComposeFile") {
+        warnings.push(
+            "dockerComposeFile: Compose-based devcontainers are not supported; set `image` in devenv.toml".into(),
+        );
+        for key in ["dockerComposeFile", "service", "runServices"] {
+            json.remove(key);
+        }
+    }
+
+    if let Some(user) = json.remove("remoteUser") {
+        let user = as_string("remoteUser", &user)?;
+        if user != "root" {
+            devenv.user_name = Some(user);
+        }
+    }
+    if let Some(user) = json.remove("containerUser") {
+        let user = as_string("containerUser", &user)?;
+        if devenv.user_name.as_deref().unwrap_or("root") != user {
+            warnings.push(format!(
+                "containerUser: devenv runs the container as root; '{user}' is not used"
+            ));
+        }
+    }
+
+    // devenv runs `commands` on every start
+    for key in ["postCreateCommand", "postStartCommand"] {
+        if let Some(cmd) = json.remove(key) {
+            devenv.commands.extend(commands(key, &cmd)?);
+            if key == "postCreateCommand" {
+                warnings.push(
+                    "postCreateCommand: imported into `commands`, which run on every start; make sure they are idempotent".into(),
+                );
+            }
+        }
+    }
+
+    // devenv sets the same variables for the container and for every exec
+    for key in ["containerEnv", "remoteEnv"] {
+        let Some(env) = json.remove(key) else {
+            continue;
+        };
+        let Value::Object(env) = env else {
+            bail!("{key} must be an object");
+        };
+        for (name, value) in env {
+            match from_local_env(&scalar_string(&value)) {
+                Some(value) => {
+                    devenv.env.insert(name, value);
+                }
+                None => warnings.push(format!(
+                    "{key}.{name}: only ${{localEnv:...}} references can be imported; ignored"
+                )),
+            }
+        }
+    }
+
+    // forwardPorts reach the container from localhost on the same port;
+    // appPort publishes like `docker run -p`
+    if let Some(ports) = json.remove("forwardPorts") {
+        let Value::Array(ports) = ports else {
+            bail!("forwardPorts must be an array");
+        };
+        for port in ports {
+            let target = match &port {
+                Value::Number(n) => n.as_u64().and_then(|n| u16::try_from(n).ok()),
+                Value::String(s) => s.strip_prefix("localhost:").and_then(|p| p.parse().ok()),
+                _ => None,
+            };
+            match target {
+                Some(target) => devenv.ports.push(Port {
+                    target,
+                    published: Some(target),
+                    host_ip: Some("127.0.0.1".into()),
+                    protocol: "tcp".into(),
+                }),
+                None => warnings.push(format!(
+                    "forwardPorts: {port} is not a port of the dev container; ignored"
+                )),
+            }
+        }
+    }
+    if let Some(ports) = json.remove("appPort") {
+        let ports = match ports {
+            Value::Array(ports) => ports,
+            port => vec![port],
+        };
+        for port in ports {
+            let spec = match &port {
+                Value::Number(n) => format!("{n}:{n}"),
+                other => scalar_string(other),
+            };
+            match Port::parse(&spec) {
+                Ok(port) => devenv.ports.push(port),
+                Err(e) => warnings.push(format!("appPort: {spec}: {e}; ignored")),
+            }
+        }
+    }
+
+    if let Some(mounts) = json.remove("mounts") {
+        let Value::Array(mounts) = mounts else {
+            bail!("mounts must be an array");
+        };
+        for mount in &mounts {
+            match import_mount(mount) {
+                Ok(mount) => devenv.mounts.push(mount),
+                Err(e) => warnings.push(format!("mounts: {e}; ignored")),
+            }
+        }
+    }
+
+    if let Some(folder) = json.remove("workspaceFolder") {
+        let folder = as_string("workspaceFolder", &folder)?;
+        if folder.trim_end_matches('/') != WORKSPACE {
+            warnings.push(format!(
+                "workspaceFolder: devenv mounts the project at {WORKSPACE}, not {folder}"
+            ));
+        }
+    }
+    for key in ["name", "$schema", "workspaceMount"] {
+        json.remove(key);
+    }
+
+    // Everything left has no devenv equivalent
+    let mut rest: Vec<_> = json.keys().cloned().collect();
+    rest.sort();
+    for key in rest {
+        warnings.push(format!("{key}: not supported by devenv; ignored"));
+    }
+    Ok(warnings)
+}
+
+/// Write `.devcontainer/devcontainer.json` for `cfg`, returning its path and a
+/// warning for every setting that has no devcontainer equivalent. An existing
+/// file is only replaced if devenv generated it, unless `force` is set.
+pub fn export(cfg: &Config, force: bool) -> Result<(PathBuf, Vec<String>)> {
+    let path = cfg.project_dir().join(CANDIDATES[0]);
+    if let Ok(existing) = fs::read_to_string(&path)
+        && !existing.starts_with(GENERATED)
+        && !force
+    {
+        bail!(
+            "{} exists and was not generated by devenv; use --force to overwrite it",
+            path.display()
+        );
+    }
+
+    let (json, warnings) = render(cfg)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, json).with_context(|| format!("Writing {}", path.display()))?;
+    Ok((path, warnings))
+}
+
+// Render devcontainer.json; paths are relative to the `.devcontainer` directory
+fn render(cfg: &Config) -> Result<(String, Vec<String>)> {
+    let dev = &cfg.devenv;
+    let project = cfg.project_dir();
+    let from_devcontainer = |path: &Path| match path.strip_prefix(project) {
+        Ok(rel) if rel.as_os_str().is_empty() => "..".to_string(),
+        Ok(rel) => format!("../{}", slash_path(rel)),
+        Err(_) => slash_path(path),
+    };
+    let mut warnings = Vec::new();
+    // `devenv init` adds `/.devenv` to .gitignore
+    if cfg.dockerfile_path().starts_with(project.join(".devenv")) {
+        warnings.push(
+            "build.dockerfile: .devenv is ignored by git, so a fresh clone has no Dockerfile to build; commit it or set `dockerfile` to a tracked path".into(),
+        );
+    }
+
+    let mut args = BTreeMap::new();
+    for (key, value) in &dev.build_args {
+        let value = to_local_env(&format!("build_args.{key}"), value, &mut warnings)
+            .with_context(|| format!("Translating build arg {key}"))?;
+        args.insert(key.clone(), value);
+    }
+    let mut container_env = BTreeMap::new();
+    for (key, value) in &dev.env {
+        let value = to_local_env(&format!("env.{key}"), value, &mut warnings)
+            .with_context(|| format!("Translating env.{key}"))?;
+        container_env.insert(key.clone(), value);
+    }
+    if dev.env_file.is_some() {
+        warnings.push(
+            "env_file: devcontainers cannot load dotenv files; add its variables to [devenv.env] or runArgs".into(),
+        );
+    }
+    if !dev.build_secrets.is_empty() {
+        warnings.push(
+            "build_secrets: devcontainers cannot pass BuildKit secrets; builds that need them will fail".into(),
+        );
+    }
+
+    let mut mounts = Vec::new();
+    for mount in &dev.mounts {
+        mounts.push(
+            export_mount(dev, mount, &mut warnings)
+                .with_context(|| format!("Translating mount {}", mount.target))?,
+        );
+    }
+
+    // Resource limits have no devcontainer property, so pass them to `docker run`
+    let mut run_args = Vec::new();
+    if let Some(res) = &dev.resources {
+        let flags = [
+            ("--cpus", res.cpus.map(|c| c.to_string())),
+            ("--memory", res.memory.clone()),
+            ("--memory-swap", res.memory_swap.clone()),
+            ("--pids-limit", res.pids_limit.map(|p| p.to_string())),
+            ("--shm-size", res.shm_size.clone()),
+        ];
+        for (flag, value) in flags {
+            if let Some(value) = value {
+                run_args.push(format!("{flag}={value}"));
+            }
+        }
+        for (name, limit) in &res.ulimits {
+            let (soft, hard) = limit.soft_hard();
+            run_args.push(format!("--ulimit={name}={soft}:{hard}"));
+        }
+    }
+
+    let mut forward_ports = Vec::new();
+    if dev.zed_remote.as_ref().is_some_and(|z| z.enabled) {
+        forward_ports.push(22);
+    }
+    for port in &dev.ports {
+        if port.protocol != "tcp" {
+            warnings.push(format!(
+                "ports: {port}: devcontainers only forward TCP ports; not exported"
+            ));
+            continue;
+        }
+        if port.published.is_some_and(|p| p != port.target) {
+            warnings.push(format!(
+                "ports: {port}: devcontainers forward to the same port on the host"
+            ));
+        }
+        if !forward_ports.contains(&port.target) {
+            forward_ports.push(port.target);
+        }
+    }
+
+    let post_create_command = match dev.commands.as_slice() {
+        [] => None,
+        commands => Some(commands.join(" && ")),
+    };
+
+    let container = DevContainer {
+        name: dev.name.clone(),
+        build: Build {
+            dockerfile: from_devcontainer(&cfg.dockerfile_path()),
+            context: from_devcontainer(&cfg.context_dir()),
+            target: dev.target.clone(),
+            args,
+        },
+        workspace_folder: WORKSPACE,
+        workspace_mount: format!("source=${{localWorkspaceFolder}},target={WORKSPACE},type=bind"),
+        remote_user: dev.non_root_user().map(String::from),
+        mounts,
+        run_args,
+        forward_ports,
+        container_env,
+        post_create_command,
+    };
+    let json = serde_json::to_string_pretty(&container)?;
+    Ok((format!("{GENERATED}\n{json}\n"), warnings))
+}
+
+// `build.dockerfile` becomes the Dockerfile template: a Dockerfile without
+// mustache tags renders to itself, so devenv builds it unchanged
+fn import_build(
+    project_dir: &Path,
+    base: &Path,
+    build: Value,
+    devenv: &mut DevEnvConfig,
+    warnings: &mut Vec<String>,
+) -> Result<()> {
+    let Value::Object(mut build) = build else {
+        bail!("build must be an object");
+    };
+    if let Some(dockerfile) = build.remove("dockerfile") {
+        let rel = normalise(&base.join(as_string("build.dockerfile", &dockerfile)?));
+        let path = project_dir.join(&rel);
+        let contents =
+            fs::read_to_string(&path).with_context(|| format!("Reading {}", path.display()))?;
+        if devenv.image.is_empty() {
+            devenv.image = base_image(&contents).unwrap_or_default();
+        }
+        devenv.dockerfile_template = Some(slash_path(&rel));
+    }
+    let context = match build.remove("context") {
+        Some(ctx) => normalise(&base.join(as_string("build.context", &ctx)?)),
+        None => base.to_path_buf(),
+    };
+    if !context.as_os_str().is_empty() {
+        devenv.context = Some(slash_path(&context));
+    }
+    if let Some(target) = build.remove("target") {
+        devenv.target = Some(as_string("build.target", &target)?);
+    }
+    if let Some(Value::Object(args)) = build.remove("args") {
+        for (key, value) in args {
+            devenv.build_args.insert(key, scalar_string(&value));
+        }
+    }
+
+    let mut rest: Vec<_> = build.keys().cloned().collect();
+    rest.sort();
+    for key in rest {
+        warnings.push(format!("build.{key}: not supported by devenv; ignored"));
+    }
+    Ok(())
+}
+
+// A mount is either a `--mount` style string or an object with `source`,
+// `target` and `type`
+fn import_mount(value: &Value) -> Result<Mount> {
+    let fields: Vec<(String, String)> = match value {
+        Value::String(spec) => spec
+            .split(',')
+            .map(|field| match field.split_once('=') {
+                Some((k, v)) => (k.trim().to_string(), v.trim().to_string()),
+                None => (field.trim().to_string(), "true".to_string()),
+            })
+            .collect(),
+        Value::Object(map) => map
+            .iter()
+            .map(|(k, v)| (k.clone(), scalar_string(v)))
+            .collect(),
+        _ => bail!("{value} is not a string or object"),
+    };
+    let mut mount = Mount {
+        kind: MountKind::Volume,
+        source: None,
+        target: String::new(),
+        read_only: false,
+    };
+    for (key, value) in fields {
+        match key.as_str() {
+            "type" => {
+                mount.kind = match value.as_str() {
+                    "bind" => MountKind::Bind,
+                    "volume" => MountKind::Volume,
+                    "tmpfs" => MountKind::Tmpfs,
+                    other => bail!("mount type '{other}' is not supported"),
+                }
+            }
+            "source" | "src" => {
+                let source = value
+                    .replace("${localWorkspaceFolder}/", "")
+                    .replace("${localWorkspaceFolder}", ".");
+                mount.source = Some(
+                    from_local_env(&source)
+                        .with_context(|| format!("source '{value}' uses an unknown variable"))?,
+                );
+            }
+            "target" | "dst" | "destination" => {
+                mount.target = value.replace("${containerWorkspaceFolder}", WORKSPACE);
+            }
+            "readonly" | "ro" => mount.read_only = value != "false",
+            // Docker Desktop performance hints
+            "consistency" => {}
+            other => bail!("mount option '{other}' is not supported"),
+        }
+    }
+    if mount.target.is_empty() {
+        bail!("{value} has no target");
+    }
+    Ok(mount)
+}
+
+// Host variables in `value`, which devcontainers spell `${localEnv:VAR}` or
+// `${localEnv:VAR:default}`
+fn to_local_env(key: &str, value: &str, warnings: &mut Vec<String>) -> Result<String> {
+    substitute(value, |name, op| {
+        let default = op.strip_prefix(":-").or_else(|| op.strip_prefix('-'));
+        Ok(match default {
+            Some(default) => format!("${{localEnv:{name}:{default}}}"),
+            None if op.is_empty() => format!("${{localEnv:{name}}}"),
+            None if op.starts_with(":?") => {
+                warnings.push(format!(
+                    "{key}: devcontainers cannot require {name} to be set; it is empty when unset"
+                ));
+                format!("${{localEnv:{name}}}")
+            }
+            None => bail!("Unsupported variable expression '${{{name}{op}}}'"),
+        })
+    })
+}
+
+// `--mount` style string with the host side in devcontainer variables
+fn export_mount(dev: &DevEnvConfig, mount: &Mount, warnings: &mut Vec<String>) -> Result<String> {
+    let mut fields = Vec::new();
+    let kind = match mount.kind {
+        MountKind::Bind => "bind",
+        MountKind::Volume => "volume",
+        MountKind::Tmpfs => "tmpfs",
+    };
+    match (mount.kind, &mount.source) {
+        (MountKind::Bind, Some(source)) => {
+            let source = match source.strip_prefix('~') {
+                Some(rest) => format!("${{HOME}}{rest}"),
+                None => source.clone(),
+            };
+            let source = to_local_env(&format!("mounts.{}", mount.target), &source, warnings)?;
+            let source = match source.trim_start_matches("./").trim_end_matches('/') {
+                _ if source.starts_with(['/', '$']) => source,
+                "" | "." => "${localWorkspaceFolder}".to_string(),
+                rel => format!("${{localWorkspaceFolder}}/{rel}"),
+            };
+            fields.push(format!("source={source}"));
+        }
+        (MountKind::Volume, Some(name)) => {
+            fields.push(format!("source=devenv-{}-{name}", dev.name))
+        }
+        _ => {}
+    }
+    fields.push(format!("target={}", mount.target));
+    fields.push(format!("type={kind}"));
+    if mount.read_only {
+        fields.push("readonly".into());
+    }
+    Ok(fields.join(","))
+}
+
+// Lifecycle commands may be a shell string, an exec-style array, or an
+// object of named commands that would run in parallel
+fn commands(key: &str, value: &Value) -> Result<Vec<String>> {
+    Ok(match value {
+        Value::String(cmd) => vec![cmd.clone()],
+        Value::Array(args) => vec![
+            args.iter()
+                .map(|a| shell_quote(&scalar_string(a)))
+                .collect::<Vec<_>>()
+                .join(" "),
+        ],
+        Value::Object(named) => named
+            .values()
+            .map(|v| commands(key, v))
+            .collect::<Result<Vec<_>>>()?
+            .concat(),
+        _ => bail!("{key} must be a string, array or object"),
+    })
+}
+
+// Base image of the first stage, for OS family inference
+fn base_image(dockerfile: &str) -> Option<String> {
+    dockerfile.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        if !words.next()?.eq_ignore_ascii_case("FROM") {
+            return None;
+        }
+        words.find(|w| !w.starts_with("--")).map(String::from)
+    })
+}
+
+// Translate `${localEnv:VAR}` and `${localEnv:VAR:default}` into devenv's
+// `${VAR}` / `${VAR:-default}`, escaping other `$`. None if the value uses
+// another kind of variable, such as `${containerEnv:PATH}`.
+fn from_local_env(value: &str) -> Option<String> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if !rest.starts_with("${") {
+            out.push_str("$$");
+            rest = &rest[1..];
+            continue;
+        }
+        let end = rest.find('}')?;
+        let var = rest[2..end].strip_prefix("localEnv:")?;
+        match var.split_once(':') {
+            Some((name, default)) => out.push_str(&format!("${{{name}:-{default}}}")),
+            None => out.push_str(&format!("${{{var}}}")),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+// Resolve `.` and `..` without touching the filesystem
+fn normalise(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for part in path.components() {
+        match part {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// Strip `//` and `/* */` comments and trailing commas from JSONC
+pub fn strip_jsonc(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = '\0';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                out.push(' ');
+            }
+            (',', _) => {
+                // Drop the comma if only whitespace/comments precede a closing bracket
+                if !matches!(next_token(chars.clone()), Some('}' | ']')) {
+                    out.push(',');
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// First character after whitespace and comments
+fn next_token(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    let mut prev = None;
+    let mut comment = None;
+    while let Some(c) = chars.next() {
+        match (comment, prev, c) {
+            (Some('/'), _, '\n') => comment = None,
+            (Some('*'), Some('*'), '/') => comment = None,
+            (Some(_), _, _) => {}
+            (None, _, '/') => match chars.next() {
+                Some(kind @ ('/' | '*')) => comment = Some(kind),
+                _ => return Some('/'),
+            },
+            (None, _, c) if c.is_whitespace() => {}
+            (None, _, c) => return Some(c),
+        }
+        prev = Some(c);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn strips_comments_and_trailing_commas() {
+        let src = r#"{
+  // line comment
+  "image": "mcr.microsoft.com/devcontainers/rust:1", /* block */
+  "url": "http://example.com/a//b",
+  "list": [1, 2, // trailing
+  ],
+  "esc": "quote \" // not a comment",
+}"#;
+        let v: Value = serde_json::from_str(&strip_jsonc(src)).unwrap();
+        assert_eq!(v["image"], "mcr.microsoft.com/devcontainers/rust:1");
+        assert_eq!(v["url"], "http://example.com/a//b");
+        assert_eq!(v["list"], serde_json::json!([1, 2]));
+        assert_eq!(v["esc"], "quote \" // not a comment");
+    }
+
+    #[test]
+    fn imports_image_user_and_commands() {
+        let td = TempDir::new().unwrap();
+        let dir = td.path().join(".devcontainer");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("devcontainer.json"),
+            r#"{
+  "name": "My App",
+  "image": "mcr.microsoft.com/devcontainers/typescript-node:20",
+  "remoteUser": "node",
+  "postCreateCommand": ["npm", "install", "--no-audit"],
+  "postStartCommand": {"a": "echo started", "b": "git config --global x y"},
+  "containerEnv": {"NODE_ENV": "development", "TOKEN": "${localEnv:TOKEN}", "COST": "$5"},
+  "remoteEnv": {"PATH": "${containerEnv:PATH}:/opt/bin", "EDITOR": "${localEnv:EDITOR:vim}"},
+  "forwardPorts": [3000, "localhost:9229", "db:5432"],
+  "appPort": ["127.0.0.1:8080:80", 4000],
+  "mounts": [
+    "source=${localEnv:HOME}/.npm,target=/home/node/.npm,type=bind,consistency=cached",
+    {"source": "node-modules", "target": "${containerWorkspaceFolder}/node_modules", "type": "volume"},
+    "source=${localWorkspaceFolder}/../shared,target=/shared,type=bind,readonly",
+    "source=cache-${devcontainerId},target=/cache,type=volume"
+  ],
+  "customizations": {"vscode": {}},
+  "features": {},
+}"#,
+        )
+        .unwrap();
+
+        let mut devenv = DevEnvConfig::default();
+        let warnings = import(td.path(), &mut devenv).unwrap();
+        assert_eq!(
+            devenv.image,
+            "mcr.microsoft.com/devcontainers/typescript-node:20"
+        );
+        assert_eq!(devenv.user_name.as_deref(), Some("node"));
+        assert_eq!(
+            devenv.commands,
+            vec![
+                "npm install --no-audit",
+                "echo started",
+                "git config --global x y"
+            ]
+        );
+        assert_eq!(devenv.env["NODE_ENV"], "development");
+        assert_eq!(devenv.env["TOKEN"], "${TOKEN}");
+        assert_eq!(devenv.env["COST"], "$$5");
+        assert_eq!(devenv.env["EDITOR"], "${EDITOR:-vim}");
+        assert!(!devenv.env.contains_key("PATH"));
+        let ports: Vec<_> = devenv.ports.iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            ports,
+            vec![
+                "127.0.0.1:3000:3000",
+                "127.0.0.1:9229:9229",
+                "127.0.0.1:8080:80",
+                "4000:4000"
+            ]
+        );
+        let mounts: Vec<_> = devenv
+            .mounts
+            .iter()
+            .map(|m| {
+                (
+                    m.kind,
+                    m.source.as_deref().unwrap(),
+                    m.target.as_str(),
+                    m.read_only,
+                )
+            })
+            .collect();
+        assert_eq!(
+            mounts,
+            vec![
+                (MountKind::Bind, "${HOME}/.npm", "/home/node/.npm", false),
+                (
+                    MountKind::Volume,
+                    "node-modules",
+                    "/workspace/node_modules",
+                    false
+                ),
+                (MountKind::Bind, "../shared", "/shared", true),
+            ]
+        );
+        assert!(warnings[0].starts_with("postCreateCommand:"));
+        assert_eq!(
+            &warnings[1..],
+            &[
+                "remoteEnv.PATH: only ${localEnv:...} references can be imported; ignored",
+                "forwardPorts: \"db:5432\" is not a port of the dev container; ignored",
+                "mounts: source 'cache-${devcontainerId}' uses an unknown variable; ignored",
+                "customizations: not supported by devenv; ignored",
+                "features: not supported by devenv; ignored",
+            ]
+        );
+    }
+
+    #[test]
+    fn imports_dockerfile_build() {
+        let td = TempDir::new().unwrap();
+        let dir = td.path().join(".devcontainer");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("Dockerfile"),
+            "ARG V=3\nFROM --platform=linux/amd64 python:3.12-bookworm AS dev\nRUN pip install uv\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("devcontainer.json"),
+            r#"{
+  "build": {"dockerfile": "Dockerfile", "context": "..", "target": "dev", "args": {"V": "3", "N": 1}, "cacheFrom": "x"},
+  "workspaceFolder": "/src"
+}"#,
+        )
+        .unwrap();
+
+        let mut devenv = DevEnvConfig::default();
+        let warnings = import(td.path(), &mut devenv).unwrap();
+        assert_eq!(devenv.image, "python:3.12-bookworm");
+        assert_eq!(
+            devenv.dockerfile_template.as_deref(),
+            Some(".devcontainer/Dockerfile")
+        );
+        assert_eq!(devenv.context, None);
+        assert_eq!(devenv.target.as_deref(), Some("dev"));
+        assert_eq!(devenv.build_args["N"], "1");
+        assert_eq!(
+            warnings,
+            vec![
+                "build.cacheFrom: not supported by devenv; ignored",
+                "workspaceFolder: devenv mounts the project at /workspace, not /src",
+            ]
+        );
+    }
+
+    fn config(dir: &Path, toml: &str) -> Config {
+        fs::write(dir.join("devenv.toml"), toml).unwrap();
+        Config::open(dir).unwrap()
+    }
+
+    #[test]
+    fn exports_generated_dockerfile_and_settings() {
+        let td = TempDir::new().unwrap();
+        let cfg = config(
+            td.path(),
+            r#"[devenv]
+name = "app"
+image = "rust:trixie"
+packages = []
+commands = ["cargo fetch", "cargo build"]
+provision_as_non_root = true
+user_name = "dev"
+target = "dev"
+ports = ["3000", "127.0.0.1:8080:80", "9229/udp"]
+
+[devenv.build_args]
+REGISTRY = "${REGISTRY:-ghcr.io}"
+PLAIN = "1"
+
+[devenv.build_secrets]
+token = "~/.token"
+
+[devenv.env]
+RUST_LOG = "debug"
+HOME_DIR = "$HOME"
+TOKEN = "${TOKEN:?needed to pull}"
+
+[devenv.resources]
+cpus = 2.5
+memory = "4g"
+ulimits = { nofile = 65536 }
+
+[[devenv.mounts]]
+source = "~/.cargo/registry"
+target = "/usr/local/cargo/registry"
+
+[[devenv.mounts]]
+source = "../shared/"
+target = "/shared"
+read_only = true
+
+[[devenv.mounts]]
+type = "volume"
+source = "target"
+target = "/workspace/target"
+
+[[devenv.mounts]]
+type = "tmpfs"
+target = "/tmp"
+
+[devenv.zed_remote]
+enabled = true
+"#,
+        );
+        let (path, warnings) = export(&cfg, false).unwrap();
+        assert_eq!(path, td.path().join(".devcontainer/devcontainer.json"));
+        assert_eq!(
+            &warnings[3..],
+            &[
+                "ports: 127.0.0.1:8080:80: devcontainers forward to the same port on the host",
+                "ports: 9229/udp: devcontainers only forward TCP ports; not exported",
+            ]
+        );
+        assert!(warnings[0].starts_with("build.dockerfile: .devenv is ignored by git"));
+        assert_eq!(
+            warnings[1],
+            "env.TOKEN: devcontainers cannot require TOKEN to be set; it is empty when unset"
+        );
+        assert!(warnings[2].starts_with("build_secrets:"));
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with(GENERATED));
+        let json: Value = serde_json::from_str(&strip_jsonc(&contents)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "name": "app",
+                "build": {
+                    "dockerfile": "../.devenv/Dockerfile",
+                    "context": "..",
+                    "target": "dev",
+                    "args": {"PLAIN": "1", "REGISTRY": "${localEnv:REGISTRY:ghcr.io}"}
+                },
+                "workspaceFolder": "/workspace",
+                "workspaceMount": "source=${localWorkspaceFolder},target=/workspace,type=bind",
+                "remoteUser": "dev",
+                "mounts": [
+                    "source=${localEnv:HOME}/.cargo/registry,target=/usr/local/cargo/registry,type=bind",
+                    "source=${localWorkspaceFolder}/../shared,target=/shared,type=bind,readonly",
+                    "source=devenv-app-target,target=/workspace/target,type=volume",
+                    "target=/tmp,type=tmpfs"
+                ],
+                "runArgs": ["--cpus=2.5", "--memory=4g", "--ulimit=nofile=65536:65536"],
+                "forwardPorts": [22, 3000, 80],
+                "containerEnv": {
+                    "HOME_DIR": "${localEnv:HOME}",
+                    "RUST_LOG": "debug",
+                    "TOKEN": "${localEnv:TOKEN}"
+                },
+                "postCreateCommand": "cargo fetch && cargo build"
+            })
+        );
+
+        // Regenerating is fine; hand-written files need --force
+        export(&cfg, false).unwrap();
+        fs::write(&path, "{}").unwrap();
+        assert!(export(&cfg, false).is_err());
+        export(&cfg, true).unwrap();
+    }
+
+    #[test]
+    fn missing_devcontainer_is_an_error() {
+        let td = TempDir::new().unwrap();
+        let err = import(td.path(), &mut DevEnvConfig::default()).unwrap_err();
+        assert!(err.to_string().starts_with("No devcontainer.json found"));
+    }
+}
@@ -0,0 +1,147 @@
+/// Lines of unchanged context shown around each change
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Render a unified line diff from `old` to `new`, or `None` when they are identical
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> Option<String> {
+    if old == new {
+        return None;
+    }
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let ops = diff_ops(&a, &b);
+
+    let mut out = format!("--- {old_label}\n+++ {new_label}\n");
+    // Line positions (in `a` and `b`) at the start of every op
+    let mut pos = Vec::with_capacity(ops.len() + 1);
+    let (mut i, mut j) = (0, 0);
+    for op in &ops {
+        pos.push((i, j));
+        match op {
+            Op::Equal => (i, j) = (i + 1, j + 1),
+            Op::Delete => i += 1,
+            Op::Insert => j += 1,
+        }
+    }
+    pos.push((i, j));
+
+    let changes: Vec<usize> = (0..ops.len()).filter(|&k| ops[k] != Op::Equal).collect();
+    let mut k = 0;
+    while k < changes.len() {
+        // Grow the hunk while the next change is close enough to share context
+        let start = changes[k].saturating_sub(CONTEXT);
+        let mut end = changes[k] + 1;
+        while k + 1 < changes.len() && changes[k + 1] <= end + 2 * CONTEXT {
+            k += 1;
+            end = changes[k] + 1;
+        }
+        let end = (end + CONTEXT).min(ops.len());
+        k += 1;
+
+        let (a0, b0) = pos[start];
+        let (a1, b1) = pos[end];
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(a0, a1 - a0),
+            range(b0, b1 - b0)
+        ));
+        for (n, op) in ops[start..end].iter().enumerate() {
+            let (i, j) = pos[start + n];
+            let line = match op {
+                Op::Equal => format!(" {}", a[i]),
+                Op::Delete => format!("-{}", a[i]),
+                Op::Insert => format!("+{}", b[j]),
+            };
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    Some(out)
+}
+
+// Hunk ranges are 1-based; an empty range points at the line before it
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{len}", start + 1),
+    }
+}
+
+// Longest-common-subsequence edit script. Dockerfiles are small, so the
+// quadratic table is fine.
+fn diff_ops(a: &[&str], b: &[&str]) -> Vec<Op> {
+    let (n, m) = (a.len(), b.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(n + m);
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && a[i] == b[j] {
+            ops.push(Op::Equal);
+            (i, j) = (i + 1, j + 1);
+        } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            ops.push(Op::Insert);
+            j += 1;
+        } else {
+            ops.push(Op::Delete);
+            i += 1;
+        }
+    }
+    // Show deletions before insertions within a change, like diff(1)
+    for k in 1..ops.len() {
+        let mut k = k;
+        while k > 0 && ops[k] == Op::Delete && ops[k - 1] == Op::Insert {
+            ops.swap(k, k - 1);
+            k -= 1;
+        }
+    }
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_inputs_have_no_diff() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "a", "b"), None);
+    }
+
+    #[test]
+    fn renders_hunks_with_context() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n10\n11\n12\n13\n";
+        let diff = unified_diff(old, new, "a/Dockerfile", "b/Dockerfile").unwrap();
+        assert_eq!(
+            diff,
+            "--- a/Dockerfile\n+++ b/Dockerfile\n\
+             @@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n\
+             @@ -10,3 +10,4 @@\n 10\n 11\n 12\n+13\n"
+        );
+    }
+
+    #[test]
+    fn merges_nearby_changes_and_handles_empty_sides() {
+        let diff = unified_diff("a\nb\nc\n", "a\nB\nc\nd\n", "x", "y").unwrap();
+        assert_eq!(diff, "--- x\n+++ y\n@@ -1,3 +1,4 @@\n a\n-b\n+B\n c\n+d\n");
+
+        let diff = unified_diff("", "new\n", "x", "y").unwrap();
+        assert_eq!(diff, "--- x\n+++ y\n@@ -0,0 +1 @@\n+new\n");
+    }
+}
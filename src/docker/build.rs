@@ -0,0 +1,275 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    io::{IsTerminal, Write},
+};
+
+use bollard::models::BuildInfo;
+use tracing::{Level, debug};
+
+/// Number of trailing build log lines kept for error reports
+const LOG_TAIL: usize = 20;
+
+/// Error returned when an image build fails
+#[derive(Debug)]
+pub struct BuildError {
+    /// Build step that was running when the build failed, e.g. `Step 3/5 : RUN ...`
+    pub step: Option<String>,
+    pub message: String,
+    /// Last lines of build output leading up to the failure
+    pub log: Vec<String>,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.step {
+            Some(step) => write!(f, "Image build failed at {step}: {}", self.message)?,
+            None => write!(f, "Image build failed: {}", self.message)?,
+        }
+        if !self.log.is_empty() {
+            write!(f, "\n\nLast build output:")?;
+            for line in &self.log {
+                write!(f, "\n  {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Tracks build output, rendering a live progress line on non-verbose terminals
+pub struct BuildMonitor {
+    step: Option<String>,
+    log: VecDeque<String>,
+    layers: BTreeMap<String, String>,
+    live: bool,
+}
+
+impl BuildMonitor {
+    pub fn new() -> Self {
+        // Verbose mode logs the raw build output instead
+        let live = !tracing::enabled!(Level::DEBUG) && std::io::stderr().is_terminal();
+        Self::with_live(live)
+    }
+
+    fn with_live(live: bool) -> Self {
+        Self {
+            step: None,
+            log: VecDeque::with_capacity(LOG_TAIL),
+            layers: BTreeMap::new(),
+            live,
+        }
+    }
+
+    /// Record a build stream message, returning an error if it reports a failure
+    pub fn handle(&mut self, info: &BuildInfo) -> Result<(), BuildError> {
+        if let Some(text) = &info.stream {
+            for line in text.lines() {
+                self.push_line(line);
+            }
+        }
+
+        if let Some(status) = &info.status {
+            match &info.id {
+                Some(id) => {
+                    debug!("{id}: {status}");
+                    self.layers.insert(id.clone(), status.clone());
+                }
+                None => debug!("{status}"),
+            }
+        }
+
+        let detail = info.error_detail.as_ref().and_then(|d| d.message.clone());
+        if let Some(message) = info.error.clone().or(detail) {
+            return Err(self.fail(message));
+        }
+
+        self.render();
+        Ok(())
+    }
+
+    /// Record a line of plain-text build output (e.g. from `docker build --progress=plain`)
+    pub fn handle_line(&mut self, line: &str) {
+        self.push_line(line);
+        self.render();
+    }
+
+    /// Last `ERROR` line reported by BuildKit, if any
+    pub fn last_error(&self) -> Option<&str> {
+        self.log.iter().rev().find_map(|l| {
+            l.strip_prefix("ERROR: ")
+                .or_else(|| l.strip_prefix("ERROR "))
+        })
+    }
+
+    fn push_line(&mut self, line: &str) {
+        let line = line.trim_end();
+        if line.is_empty() {
+            return;
+        }
+        debug!("{line}");
+        if line.starts_with("Step ") {
+            self.step = Some(line.to_string());
+            self.layers.clear();
+        } else if let Some(step) = buildkit_step(line) {
+            self.step = Some(step.to_string());
+        }
+        if self.log.len() == LOG_TAIL {
+            self.log.pop_front();
+        }
+        self.log.push_back(line.to_string());
+    }
+
+    /// Convert a failure into a `BuildError` carrying the current step and log tail
+    pub fn fail(&mut self, message: impl Into<String>) -> BuildError {
+        self.clear();
+        BuildError {
+            step: self.step.clone(),
+            message: message.into(),
+            log: self.log.iter().cloned().collect(),
+        }
+    }
+
+    /// Remove the progress line once the build has finished
+    pub fn finish(&mut self) {
+        self.clear();
+    }
+
+    fn status_line(&self) -> String {
+        let mut line = match &self.step {
+            // "Step 3/5 : RUN foo" -> "[3/5] RUN foo"
+            Some(step) => match step.trim_start_matches("Step ").split_once(" : ") {
+                Some((n, text)) => format!("[{n}] {text}"),
+                None => step.clone(),
+            },
+            None => "Preparing build context".to_string(),
+        };
+        if !self.layers.is_empty() {
+            let done = self
+                .layers
+                .values()
+                .filter(|s| s == &"Pull complete" || s == &"Already exists")
+                .count();
+            line.push_str(&format!(" (layers {done}/{})", self.layers.len()));
+        }
+        line
+    }
+
+    fn render(&self) {
+        if !self.live {
+            return;
+        }
+        let width = crossterm::terminal::size()
+            .map(|(cols, _)| cols as usize)
+            .unwrap_or(80);
+        let line: String = self
+            .status_line()
+            .chars()
+            .take(width.saturating_sub(1))
+            .collect();
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "\r\x1b[2K{line}");
+        let _ = stderr.flush();
+    }
+
+    fn clear(&self) {
+        if self.live {
+            let mut stderr = std::io::stderr();
+            let _ = write!(stderr, "\r\x1b[2K");
+            let _ = stderr.flush();
+        }
+    }
+}
+
+// BuildKit plain progress: "#7 [stage 3/5] RUN foo" -> "[stage 3/5] RUN foo"
+fn buildkit_step(line: &str) -> Option<&str> {
+    let (id, rest) = line.strip_prefix('#')?.split_once(' ')?;
+    if !id.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (label, _) = rest.strip_prefix('[')?.split_once("] ")?;
+    let (n, total) = label.rsplit(' ').next()?.split_once('/')?;
+    let numeric = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    (numeric(n) && numeric(total)).then_some(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::models::ErrorDetail;
+
+    fn stream(text: &str) -> BuildInfo {
+        BuildInfo {
+            stream: Some(text.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tracks_steps_and_layers() {
+        let mut mon = BuildMonitor::with_live(false);
+        mon.handle(&stream("Step 1/3 : FROM debian\n")).unwrap();
+        for (id, status) in [("a1", "Pull complete"), ("b2", "Downloading")] {
+            mon.handle(&BuildInfo {
+                id: Some(id.into()),
+                status: Some(status.into()),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+        assert_eq!(mon.status_line(), "[1/3] FROM debian (layers 1/2)");
+
+        // A new step resets layer progress
+        mon.handle(&stream("Step 2/3 : RUN apt update\n")).unwrap();
+        assert_eq!(mon.status_line(), "[2/3] RUN apt update");
+    }
+
+    #[test]
+    fn parses_buildkit_plain_output() {
+        let mut mon = BuildMonitor::with_live(false);
+        mon.handle_line("#1 [internal] load build definition from Dockerfile");
+        assert_eq!(mon.status_line(), "Preparing build context");
+        mon.handle_line("#6 [dev 2/4] RUN --mount=type=secret,id=npm npm ci");
+        assert_eq!(
+            mon.status_line(),
+            "[dev 2/4] RUN --mount=type=secret,id=npm npm ci"
+        );
+        mon.handle_line("#6 0.512 npm ERR! 401");
+        mon.handle_line("ERROR: failed to solve: process did not complete successfully");
+        assert_eq!(
+            mon.last_error(),
+            Some("failed to solve: process did not complete successfully")
+        );
+    }
+
+    #[test]
+    fn error_detail_fails_with_step_and_log_tail() {
+        let mut mon = BuildMonitor::with_live(false);
+        mon.handle(&stream("Step 3/5 : RUN apt install -y nope\n"))
+            .unwrap();
+        for i in 0..30 {
+            mon.handle(&stream(&format!("line {i}\n"))).unwrap();
+        }
+        let err = mon
+            .handle(&BuildInfo {
+                error_detail: Some(ErrorDetail {
+                    code: Some(100),
+                    message: Some("returned a non-zero code: 100".into()),
+                }),
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.step.as_deref(),
+            Some("Step 3/5 : RUN apt install -y nope")
+        );
+        assert_eq!(err.log.len(), LOG_TAIL);
+        assert_eq!(err.log.last().map(String::as_str), Some("line 29"));
+        let msg = err.to_string();
+        assert!(msg.starts_with(
+            "Image build failed at Step 3/5 : RUN apt install -y nope: returned a non-zero code: 100"
+        ));
+        assert!(msg.contains("\n  line 29"));
+    }
+}
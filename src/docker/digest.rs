@@ -0,0 +1,233 @@
+use std::{fs, io};
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use super::{BuildOptions, context_entries, ignore::DockerIgnore};
+use crate::util::slash_path;
+
+/// Number of hex characters of the digest used in image tags
+pub const TAG_LEN: usize = 12;
+
+/// Hash everything that determines the image: the Dockerfile, build target,
+/// build args, secret ids and the context files its COPY/ADD steps reference.
+/// Secret contents are deliberately left out.
+pub fn image_digest(opts: &BuildOptions) -> Result<String> {
+    let dockerfile = fs::read_to_string(&opts.dockerfile)
+        .with_context(|| format!("Reading {}", opts.dockerfile.display()))?;
+
+    let mut hasher = Sha256::new();
+    field(&mut hasher, "dockerfile", dockerfile.as_bytes());
+    field(
+        &mut hasher,
+        "target",
+        opts.target.as_deref().unwrap_or_default().as_bytes(),
+    );
+    for (key, value) in &opts.build_args {
+        field(&mut hasher, "arg", format!("{key}={value}").as_bytes());
+    }
+    for id in opts.secrets.keys() {
+        field(&mut hasher, "secret", id.as_bytes());
+    }
+
+    let sources = copy_sources(&dockerfile);
+    if !sources.is_empty() {
+        hash_context(&mut hasher, opts, &sources)?;
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+// Length-prefix each field so adjacent values can't run into each other
+fn field(hasher: &mut Sha256, name: &str, value: &[u8]) {
+    hasher.update(name.as_bytes());
+    hasher.update((value.len() as u64).to_le_bytes());
+    hasher.update(value);
+}
+
+fn hash_context(hasher: &mut Sha256, opts: &BuildOptions, sources: &[String]) -> Result<()> {
+    let dir = opts.context_dir.as_path();
+    let ignore = DockerIgnore::open(dir)?;
+    // Source patterns share .dockerignore's matching rules: a pattern
+    // selects a path when it matches the path or one of its parents
+    let selected = DockerIgnore::parse(&sources.join("\n"));
+    let whole_context = sources
+        .iter()
+        .any(|s| s.trim_matches(['.', '/']).is_empty());
+    // The same walk as the context tar, so every file sent is hashed
+    let (dockerfile, _) = opts.context_dockerfile();
+    let mut entries: Vec<_> = context_entries(dir, &ignore, &dockerfile)
+        .filter(|e| e.file_type().is_file())
+        .collect();
+    entries.retain(|e| {
+        whole_context
+            || e.path()
+                .strip_prefix(dir)
+                .is_ok_and(|rel| selected.is_excluded(&slash_path(rel)))
+    });
+
+    for entry in entries {
+        let rel = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+        field(hasher, "path", rel.to_string_lossy().as_bytes());
+        let mut file = fs::File::open(entry.path())
+            .with_context(|| format!("Reading {}", entry.path().display()))?;
+        hasher.update(b"content");
+        io::copy(&mut file, hasher)?;
+    }
+    Ok(())
+}
+
+// Collect local sources of COPY/ADD instructions. Copies from other stages
+// (`--from`) and remote URLs don't depend on the context.
+fn copy_sources(dockerfile: &str) -> Vec<String> {
+    let mut sources = Vec::new();
+    let mut instruction = String::new();
+    for line in dockerfile.lines() {
+        let line = line.trim();
+        if instruction.is_empty() && (line.is_empty() || line.starts_with('#')) {
+            continue;
+        }
+        match line.strip_suffix('\\') {
+            Some(cont) => {
+                instruction.push_str(cont);
+                instruction.push(' ');
+                continue;
+            }
+            None => instruction.push_str(line),
+        }
+
+        let mut words = instruction.split_whitespace();
+        let keyword = words.next().unwrap_or_default().to_ascii_uppercase();
+        if keyword == "COPY" || keyword == "ADD" {
+            let args: Vec<&str> = words.collect();
+            if !args.iter().any(|a| a.starts_with("--from")) {
+                let args: Vec<String> = if args.first().is_some_and(|a| a.starts_with('[')) {
+                    serde_json::from_str(args.join(" ").trim()).unwrap_or_default()
+                } else {
+                    args.iter()
+                        .filter(|a| !a.starts_with("--"))
+                        .map(|a| a.to_string())
+                        .collect()
+                };
+                if let Some((_dest, srcs)) = args.split_last() {
+                    sources.extend(
+                        srcs.iter()
+                            .filter(|s| !s.contains("://") && !s.starts_with("git@"))
+                            .cloned(),
+                    );
+                }
+            }
+        }
+        instruction.clear();
+    }
+    sources
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+    use tempfile::TempDir;
+
+    fn opts(dir: &Path) -> BuildOptions {
+        BuildOptions {
+            context_dir: dir.to_path_buf(),
+            dockerfile: dir.join("Dockerfile"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_copy_and_add_sources() {
+        let df = r#"
+FROM rust AS build
+COPY --chown=dev:dev certs/ /usr/local/share/ca-certificates/
+ADD https://example.com/tool.tgz /opt/
+COPY --from=build /app /app
+copy ["scripts/setup.sh", "Cargo.*", "/tmp/"]
+ADD a.txt \
+    b.txt /data/
+RUN echo COPY nothing
+"#;
+        assert_eq!(
+            copy_sources(df),
+            vec!["certs/", "scripts/setup.sh", "Cargo.*", "a.txt", "b.txt"]
+        );
+    }
+
+    #[test]
+    fn digest_tracks_dockerfile_args_and_copied_files() {
+        let td = TempDir::new().unwrap();
+        let dir = td.path();
+        fs::write(dir.join("Dockerfile"), "FROM debian\nCOPY certs /certs\n").unwrap();
+        fs::create_dir_all(dir.join("certs")).unwrap();
+        fs::write(dir.join("certs/ca.crt"), "one").unwrap();
+        fs::write(dir.join("unrelated.txt"), "x").unwrap();
+
+        let base = image_digest(&opts(dir)).unwrap();
+        assert_eq!(base.len(), 64);
+        assert_eq!(base, image_digest(&opts(dir)).unwrap());
+
+        // Files that no COPY references don't matter
+        fs::write(dir.join("unrelated.txt"), "y").unwrap();
+        assert_eq!(base, image_digest(&opts(dir)).unwrap());
+
+        // Copied files do
+        fs::write(dir.join("certs/ca.crt"), "two").unwrap();
+        let changed = image_digest(&opts(dir)).unwrap();
+        assert_ne!(base, changed);
+
+        // As do build args and targets
+        let mut o = opts(dir);
+        o.build_args.insert("A".into(), "1".into());
+        assert_ne!(changed, image_digest(&o).unwrap());
+        let mut o = opts(dir);
+        o.target = Some("dev".into());
+        assert_ne!(changed, image_digest(&o).unwrap());
+
+        // Secret ids count, their file contents don't
+        let mut o = opts(dir);
+        o.secrets
+            .insert("tok".into(), PathBuf::from("/nonexistent"));
+        assert_ne!(changed, image_digest(&o).unwrap());
+    }
+
+    #[test]
+    fn digest_ignores_dockerignored_files() {
+        let td = TempDir::new().unwrap();
+        let dir = td.path();
+        fs::write(dir.join("Dockerfile"), "FROM debian\nCOPY . /src\n").unwrap();
+        fs::write(dir.join(".dockerignore"), "*.log\n").unwrap();
+        fs::write(dir.join("main.rs"), "fn main() {}").unwrap();
+        let base = image_digest(&opts(dir)).unwrap();
+
+        fs::write(dir.join("build.log"), "noise").unwrap();
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::write(dir.join("target/app"), "bin").unwrap();
+        assert_eq!(base, image_digest(&opts(dir)).unwrap());
+
+        fs::write(dir.join("main.rs"), "fn main() { }").unwrap();
+        assert_ne!(base, image_digest(&opts(dir)).unwrap());
+    }
+
+    #[test]
+    fn digest_hashes_files_reincluded_below_ignored_directories() {
+        let td = TempDir::new().unwrap();
+        let dir = td.path();
+        fs::write(dir.join("Dockerfile"), "FROM debian\nCOPY . /src\n").unwrap();
+        fs::write(dir.join(".dockerignore"), "vendor\n!vendor/keep/lib.rs\n").unwrap();
+        fs::create_dir_all(dir.join("vendor/keep")).unwrap();
+        fs::write(dir.join("vendor/keep/lib.rs"), "v1").unwrap();
+        fs::write(dir.join("vendor/other.rs"), "x").unwrap();
+        let base = image_digest(&opts(dir)).unwrap();
+
+        fs::write(dir.join("vendor/other.rs"), "y").unwrap();
+        assert_eq!(base, image_digest(&opts(dir)).unwrap());
+        fs::write(dir.join("vendor/keep/lib.rs"), "v2").unwrap();
+        assert_ne!(base, image_digest(&opts(dir)).unwrap());
+    }
+}
@@ -6,6 +6,9 @@ use std::{
 
 use anyhow::{Context, Result};
 use mustache::{Data, compile_str, to_data};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::DevEnvConfig, diff::unified_diff};
 
 const FILENAME: &str = "Dockerfile";
 const DOCKERFILE: &str = r#"
@@ -17,46 +20,108 @@ FROM {{image}}
 WORKDIR /workspace
 
 # Update environment
-RUN {{update_env}}
+RUN {{{update_env}}}
+{{#pre_install}}
+
+{{{.}}}
+{{/pre_install}}
 
 # Install packages
-RUN {{package_inst}}{{#packages}} {{.}}{{/packages}}
+RUN {{{package_inst}}}{{#packages}} {{{.}}}{{/packages}}
+{{#user}}
+
+# Create non-root user with passwordless sudo
+RUN (getent group {{gid}} || groupadd -g {{gid}} {{name}}) \
+    && (id -u {{name}} >/dev/null 2>&1 || useradd -m -o -u {{uid}} -g {{gid}} -s /bin/bash {{name}}) \
+    && mkdir -p /home/{{name}}/.ssh && chown -R {{uid}}:{{gid}} /home/{{name}} \
+    && echo '{{name}} ALL=(ALL) NOPASSWD:ALL' > /etc/sudoers.d/{{name}} \
+    && chmod 0440 /etc/sudoers.d/{{name}}
+{{/user}}
+{{#post_install}}
+
+{{{.}}}
+{{/post_install}}
+{{#final}}
+
+{{{.}}}
+{{/final}}
 
 # Sleep indefinitely to prevent the container from stopping
 CMD ["sleep", "infinity"]
 "#;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Default, PartialEq)]
 pub struct Dockerfile(String);
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum OsFamily {
-    // Alpine, // @todo
+    Alpine,
     Debian,
-    // Rhel, // @todo
+    Rhel,
 }
 
 impl OsFamily {
+    /// Infer the OS family from a Docker image reference, defaulting to Debian
+    pub fn from_image(image: &str) -> Self {
+        // Drop any digest, then split repository (incl. registry host) from tag
+        let image = image.split('@').next().unwrap_or(image);
+        let (repo, tag) = match image.rsplit_once(':') {
+            Some((repo, tag)) if !tag.contains('/') => (repo, tag),
+            _ => (image, ""),
+        };
+        let name = repo.rsplit('/').next().unwrap_or(repo);
+
+        if name == "alpine" || tag.split(['-', '.']).any(|t| t.starts_with("alpine")) {
+            return Self::Alpine;
+        }
+
+        const RHEL_NAMES: &[&str] = &[
+            "fedora",
+            "centos",
+            "rockylinux",
+            "almalinux",
+            "oraclelinux",
+            "amazonlinux",
+        ];
+        if RHEL_NAMES.contains(&name)
+            || name.starts_with("ubi")
+            || repo
+                .split('/')
+                .any(|p| p == "redhat" || p.starts_with("ubi"))
+            || tag
+                .split('-')
+                .any(|t| RHEL_NAMES.contains(&t) || t.starts_with("ubi"))
+        {
+            return Self::Rhel;
+        }
+
+        Self::Debian
+    }
+
     fn update_env(&self) -> &'static str {
         match self {
-            // Self::Alpine => "apt -U upgrade",
+            Self::Alpine => "apk update && apk upgrade",
             Self::Debian => "apt update && apt upgrade -y",
-            // Self::Rhel => "dnf update && dnf upgrade -y",
+            // Minimal RHEL images (e.g. ubi-minimal) only ship microdnf
+            Self::Rhel => "$(command -v dnf || command -v microdnf) upgrade -y",
         }
     }
 
     fn package_inst(&self) -> &'static str {
         match self {
-            // Self::Alpine => "apk add --no-cache",
+            Self::Alpine => "apk add --no-cache",
             Self::Debian => "apt install -y",
-            // Self::Rhel => "dnf install -y",
+            Self::Rhel => "$(command -v dnf || command -v microdnf) install -y",
         }
     }
 
     fn base_packages(&self) -> &'static [&'static str] {
         match self {
-            // Self::Alpine => &["curl", "ca-certificates", "git", "sudo"],
+            Self::Alpine => &["bash", "curl", "ca-certificates", "git", "shadow", "sudo"],
             Self::Debian => &["curl", "ca-certificates", "git", "sudo"],
-            // Self::Rhel => &["curl", "ca-certificates", "git", "sudo"],
+            // curl-minimal already provides curl and conflicts with the full package
+            Self::Rhel => &["ca-certificates", "git", "shadow-utils", "sudo"],
         }
     }
 }
@@ -73,9 +138,12 @@ impl Dockerfile {
         Ok(Self(contents))
     }
 
-    pub fn create(image: &str, packages: &[String], os: OsFamily) -> Result<Self> {
+    /// Render the Dockerfile for `dev`. A `dockerfile_template` is resolved
+    /// relative to `project_dir` and replaces the built-in template.
+    pub fn create(dev: &DevEnvConfig, project_dir: &Path) -> Result<Self> {
+        let os = dev.os_family();
         let mut data = HashMap::new();
-        data.insert("image".into(), Data::String(image.into()));
+        data.insert("image".into(), Data::String(dev.image.clone()));
         data.insert("update_env".into(), Data::String(os.update_env().into()));
         data.insert(
             "package_inst".into(),
@@ -83,45 +151,90 @@ impl Dockerfile {
         );
 
         let mut pkgs: Vec<String> = os.base_packages().iter().map(|s| (*s).into()).collect();
-        packages.iter().for_each(|pkg| pkgs.push(pkg.into()));
+        dev.packages.iter().for_each(|pkg| pkgs.push(pkg.into()));
         data.insert("packages".into(), to_data(pkgs).unwrap());
 
-        // @todo
         // Optional: create a non-root user inside the image
-        // if let Some(user) = dev
-        //     .user_name
-        //     .as_deref()
-        //     .or_else(|| dev.zed_remote.as_ref().and_then(|z| z.ssh_user.as_deref()))
-        //     && user != "root"
-        // {
-        //     let uid = dev.user_uid.unwrap_or(1000);
-        //     let gid = dev.user_gid.unwrap_or(uid);
-        //     lines.push(format!(
-        //         "RUN (getent group {gid} || groupadd -g {gid} {user}) || true"
-        //     ));
-        //     lines.push(format!("RUN (id -u {user} >/dev/null 2>&1 || useradd -m -u {uid} -g {gid} -s /bin/bash {user}) || true"));
-        //     lines.push(format!(
-        //         "RUN mkdir -p /home/{user}/.ssh && chown -R {user}:{user} /home/{user}"
-        //     ));
-        // }
-        // lines.push("RUN mkdir -p /root/.ssh && chmod 700 /root/.ssh".into());
-
-        let tpl = compile_str(DOCKERFILE).expect("Dockerfile template broken");
-        let contents = tpl.render_data_to_string(&Data::Map(data)).unwrap();
+        if let Some(user) = dev.non_root_user() {
+            let uid = dev.user_uid.unwrap_or(1000);
+            let gid = dev.user_gid.unwrap_or(uid);
+            let mut u = HashMap::new();
+            u.insert("name".into(), Data::String(user.into()));
+            u.insert("uid".into(), Data::String(uid.to_string()));
+            u.insert("gid".into(), Data::String(gid.to_string()));
+            data.insert("user".into(), Data::Map(u));
+        }
+
+        // User-supplied fragments, normalised so whitespace edits don't count as drift
+        for (key, snippet) in [
+            ("pre_install", &dev.pre_install),
+            ("post_install", &dev.post_install),
+            ("final", &dev.final_steps),
+        ] {
+            if let Some(snippet) = snippet.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+                data.insert(key.into(), Data::String(snippet.into()));
+            }
+        }
+
+        let contents = match &dev.dockerfile_template {
+            Some(template) => {
+                let path = project_dir.join(template);
+                let src = fs::read_to_string(&path)
+                    .with_context(|| format!("Reading {}", path.display()))?;
+                let tpl = compile_str(&src)
+                    .with_context(|| format!("Parsing template {}", path.display()))?;
+                tpl.render_data_to_string(&Data::Map(data))
+                    .with_context(|| format!("Rendering template {}", path.display()))?
+            }
+            None => {
+                let tpl = compile_str(DOCKERFILE).expect("Dockerfile template broken");
+                let rendered = tpl.render_data_to_string(&Data::Map(data)).unwrap();
+                collapse_blank_lines(&rendered)
+            }
+        };
 
         Ok(Self(contents))
     }
 
+    /// Unified diff from this Dockerfile to `expected`, or `None` when they match
+    pub fn diff(&self, expected: &Dockerfile, name: &str) -> Option<String> {
+        unified_diff(
+            &self.0,
+            &expected.0,
+            &format!("{name} (on disk)"),
+            &format!("{name} (from devenv.toml)"),
+        )
+    }
+
     pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
-        fs::write(make_path(path), &self.0)?;
+        let path = make_path(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, &self.0)?;
         Ok(())
     }
 }
 
+// Skipped template sections can leave consecutive blank lines behind
+fn collapse_blank_lines(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for line in s.split_inclusive('\n') {
+        if line.trim().is_empty() && out.ends_with("\n\n") {
+            continue;
+        }
+        out.push_str(line);
+    }
+    out
+}
+
+// Accept either a directory (containing a `Dockerfile`) or a file path
 fn make_path(path: impl AsRef<Path>) -> PathBuf {
-    match path.as_ref().file_name() {
-        Some(name) if name.to_str() == Some(FILENAME) => path.as_ref().to_path_buf(),
-        _ => path.as_ref().join(FILENAME),
+    let path = path.as_ref();
+    if path.is_dir() {
+        path.join(FILENAME)
+    } else {
+        path.to_path_buf()
     }
 }
 
@@ -130,30 +243,173 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    fn dev(image: &str, packages: &[&str]) -> DevEnvConfig {
+        DevEnvConfig {
+            image: image.into(),
+            packages: packages.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn creates_expected_dockerfile_contents() {
-        let extra = vec!["htop".to_string(), "ripgrep".to_string()];
-        let df = Dockerfile::create("debian:bookworm", &extra, OsFamily::Debian).unwrap();
+        let df = Dockerfile::create(
+            &dev("debian:bookworm", &["htop", "ripgrep"]),
+            Path::new("."),
+        )
+        .unwrap();
         let s = df.0;
         assert!(s.contains("FROM debian:bookworm"));
         // Base packages + extras in single line
         assert!(s.contains("RUN apt install -y curl ca-certificates git sudo htop ripgrep"));
         assert!(s.contains("CMD [\"sleep\", \"infinity\"]"));
+        // Shell operators must not be HTML-escaped by the template engine
+        assert!(s.contains("RUN apt update && apt upgrade -y"));
+    }
+
+    #[test]
+    fn creates_alpine_and_rhel_dockerfiles() {
+        let df = Dockerfile::create(&dev("alpine:3", &["htop"]), Path::new(".")).unwrap();
+        assert!(df.0.contains("RUN apk update && apk upgrade"));
+        assert!(
+            df.0.contains("RUN apk add --no-cache bash curl ca-certificates git shadow sudo htop")
+        );
+
+        let df = Dockerfile::create(&dev("fedora:41", &[]), Path::new(".")).unwrap();
+        assert!(df.0.contains("RUN $(command -v dnf || command -v microdnf) upgrade -y"));
+        assert!(df.0.contains(
+            "RUN $(command -v dnf || command -v microdnf) install -y ca-certificates git shadow-utils sudo"
+        ));
+        assert!(!df.0.contains("curl"));
+    }
+
+    #[test]
+    fn creates_non_root_user_when_configured() {
+        let df = Dockerfile::create(&dev("debian:bookworm", &[]), Path::new(".")).unwrap();
+        assert!(!df.0.contains("useradd"));
+
+        let mut cfg = dev("debian:bookworm", &[]);
+        cfg.user_name = Some("dev".into());
+        cfg.user_uid = Some(1001);
+        let df = Dockerfile::create(&cfg, Path::new(".")).unwrap();
+        assert!(df.0.contains("getent group 1001 || groupadd -g 1001 dev"));
+        assert!(df.0.contains("useradd -m -o -u 1001 -g 1001 -s /bin/bash dev"));
+        assert!(df.0.contains("echo 'dev ALL=(ALL) NOPASSWD:ALL' > /etc/sudoers.d/dev"));
+
+        // uid/gid changes are reflected in the rendered file (drift check)
+        cfg.user_gid = Some(2000);
+        assert_ne!(df, Dockerfile::create(&cfg, Path::new(".")).unwrap());
+
+        // Root is never created
+        cfg.user_name = Some("root".into());
+        assert!(
+            !Dockerfile::create(&cfg, Path::new("."))
+                .unwrap()
+                .0
+                .contains("useradd")
+        );
+    }
+
+    #[test]
+    fn renders_user_fragments_in_order() {
+        let mut cfg = dev("debian:bookworm", &[]);
+        cfg.pre_install = Some("ENV PIP_INDEX_URL=https://pypi.local\n".into());
+        cfg.post_install = Some(
+            "COPY certs/ca.crt /usr/local/share/ca-certificates/\nRUN update-ca-certificates"
+                .into(),
+        );
+        cfg.final_steps = Some("USER dev".into());
+        let s = Dockerfile::create(&cfg, Path::new(".")).unwrap().0;
+
+        let pre = s.find("ENV PIP_INDEX_URL=https://pypi.local").unwrap();
+        let install = s.find("RUN apt install -y").unwrap();
+        let post = s.find("RUN update-ca-certificates").unwrap();
+        let fin = s.find("USER dev").unwrap();
+        let cmd = s.find("CMD [").unwrap();
+        assert!(pre < install && install < post && post < fin && fin < cmd);
+        assert!(s.contains("COPY certs/ca.crt /usr/local/share/ca-certificates/\n"));
+        assert!(!s.contains("\n\n\n"));
+
+        // Rendering is deterministic and ignores surrounding whitespace
+        let again = Dockerfile::create(&cfg, Path::new(".")).unwrap();
+        assert_eq!(s, again.0);
+        cfg.final_steps = Some("\nUSER dev\n\n".into());
+        assert_eq!(s, Dockerfile::create(&cfg, Path::new(".")).unwrap().0);
+    }
+
+    #[test]
+    fn custom_template_replaces_builtin() {
+        let td = TempDir::new().unwrap();
+        std::fs::write(
+            td.path().join("devenv.Dockerfile.tpl"),
+            "FROM {{image}}\nRUN {{{package_inst}}}{{#packages}} {{{.}}}{{/packages}}\n",
+        )
+        .unwrap();
+        let mut cfg = dev("alpine:3", &["jq"]);
+        cfg.dockerfile_template = Some("devenv.Dockerfile.tpl".into());
+        let df = Dockerfile::create(&cfg, td.path()).unwrap();
+        assert_eq!(
+            df.0,
+            "FROM alpine:3\nRUN apk add --no-cache bash curl ca-certificates git shadow sudo jq\n"
+        );
+
+        cfg.dockerfile_template = Some("missing.tpl".into());
+        assert!(Dockerfile::create(&cfg, td.path()).is_err());
+    }
+
+    #[test]
+    fn infers_os_family_from_image() {
+        assert_eq!(OsFamily::from_image("alpine:3"), OsFamily::Alpine);
+        assert_eq!(OsFamily::from_image("node:20-alpine"), OsFamily::Alpine);
+        assert_eq!(OsFamily::from_image("rust:alpine3.20"), OsFamily::Alpine);
+        assert_eq!(OsFamily::from_image("fedora"), OsFamily::Rhel);
+        assert_eq!(
+            OsFamily::from_image("quay.io/centos/centos:stream9"),
+            OsFamily::Rhel
+        );
+        assert_eq!(
+            OsFamily::from_image("registry.access.redhat.com/ubi9/ubi-minimal"),
+            OsFamily::Rhel
+        );
+        assert_eq!(OsFamily::from_image("rockylinux:9"), OsFamily::Rhel);
+        assert_eq!(OsFamily::from_image("rust:trixie"), OsFamily::Debian);
+        assert_eq!(
+            OsFamily::from_image("localhost:5000/tools"),
+            OsFamily::Debian
+        );
+        assert_eq!(
+            OsFamily::from_image("debian:bookworm-slim"),
+            OsFamily::Debian
+        );
     }
 
     #[test]
     fn equality_depends_on_rendered_contents() {
-        let df1 = Dockerfile::create("img", &[], OsFamily::Debian).unwrap();
-        let df2 = Dockerfile::create("img", &[], OsFamily::Debian).unwrap();
-        let df3 = Dockerfile::create("img", &["vim".to_string()], OsFamily::Debian).unwrap();
+        let df1 = Dockerfile::create(&dev("img", &[]), Path::new(".")).unwrap();
+        let df2 = Dockerfile::create(&dev("img", &[]), Path::new(".")).unwrap();
+        let df3 = Dockerfile::create(&dev("img", &["vim"]), Path::new(".")).unwrap();
         assert_eq!(df1, df2);
         assert_ne!(df1, df3);
     }
 
+    #[test]
+    fn diff_shows_drifted_lines() {
+        let df1 = Dockerfile::create(&dev("img", &[]), Path::new(".")).unwrap();
+        let df2 = Dockerfile::create(&dev("img", &["vim"]), Path::new(".")).unwrap();
+        assert_eq!(df1.diff(&df1, "Dockerfile"), None);
+
+        let diff = df1.diff(&df2, ".devenv/Dockerfile").unwrap();
+        assert!(diff.starts_with(
+            "--- .devenv/Dockerfile (on disk)\n+++ .devenv/Dockerfile (from devenv.toml)\n@@ "
+        ));
+        assert!(diff.contains("\n-RUN apt install -y curl ca-certificates git sudo\n"));
+        assert!(diff.contains("\n+RUN apt install -y curl ca-certificates git sudo vim\n"));
+    }
+
     #[test]
     fn write_and_open_roundtrip_and_exists() {
         let td = TempDir::new().unwrap();
-        let df = Dockerfile::create("base", &[], OsFamily::Debian).unwrap();
+        let df = Dockerfile::create(&dev("base", &[]), Path::new(".")).unwrap();
         assert!(!Dockerfile::exists(td.path()));
         df.write(td.path()).unwrap();
         assert!(Dockerfile::exists(td.path()));
@@ -164,5 +420,12 @@ mod tests {
         let file_path = td.path().join(FILENAME);
         let read2 = Dockerfile::open(&file_path).unwrap();
         assert_eq!(df, read2);
+
+        // Custom file names and missing parent directories are supported
+        let custom = td.path().join(".devenv/dev.Dockerfile");
+        assert!(!Dockerfile::exists(&custom));
+        df.write(&custom).unwrap();
+        assert!(Dockerfile::exists(&custom));
+        assert_eq!(df, Dockerfile::open(&custom).unwrap());
     }
 }
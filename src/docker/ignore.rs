@@ -0,0 +1,265 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::util::slash_path;
+
+const FILENAME: &str = ".dockerignore";
+
+/// Build output and cache directories that are never useful in a build context.
+/// These are evaluated before `.dockerignore`, so `!target` re-includes them.
+const DEFAULT_PATTERNS: &[&str] = &[
+    ".git",
+    ".devenv/zed_ed25519*",
+    "**/target",
+    "**/node_modules",
+    "**/__pycache__",
+    "**/.venv",
+    "**/.mypy_cache",
+    "**/.pytest_cache",
+    "**/.gradle",
+];
+
+/// Matcher for `.dockerignore` files, following Docker's pattern semantics
+#[derive(Debug, Default)]
+pub struct DockerIgnore {
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Debug)]
+struct Pattern {
+    segments: Vec<String>,
+    negate: bool,
+}
+
+impl DockerIgnore {
+    /// Load the built-in defaults followed by `<dir>/.dockerignore` if present
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let mut this = Self::parse(&DEFAULT_PATTERNS.join("\n"));
+        let path = dir.as_ref().join(FILENAME);
+        if path.exists() {
+            let contents =
+                fs::read_to_string(&path).with_context(|| format!("Reading {}", path.display()))?;
+            this.patterns.extend(Self::parse(&contents).patterns);
+        }
+        Ok(this)
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let patterns = contents
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .filter_map(|line| {
+                let (negate, pat) = match line.strip_prefix('!') {
+                    Some(rest) => (true, rest.trim()),
+                    None => (false, line),
+                };
+                let segments: Vec<String> = pat
+                    .split('/')
+                    .filter(|s| !s.is_empty() && *s != ".")
+                    .map(String::from)
+                    .collect();
+                (!segments.is_empty()).then_some(Pattern { segments, negate })
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    /// Whether a `!` pattern may re-include a path below the directory `rel`
+    pub fn may_reinclude_below(&self, rel: &str) -> bool {
+        let parts: Vec<&str> = rel.split('/').filter(|s| !s.is_empty()).collect();
+        self.patterns
+            .iter()
+            .any(|p| p.negate && may_match_below(&p.segments, &parts))
+    }
+
+    /// Walk the context at `dir`, yielding the entries sent to Docker in file
+    /// name order. Paths in `always`, relative to `dir`, are sent even when
+    /// excluded. An excluded directory is only entered when it holds one of
+    /// them or a `!` pattern may re-include something in it.
+    pub fn walk<'a>(
+        &'a self,
+        dir: &'a Path,
+        always: Vec<&'a Path>,
+    ) -> impl Iterator<Item = DirEntry> + 'a {
+        let kept = always.clone();
+        WalkDir::new(dir)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(move |e| {
+                let Ok(rel) = e.path().strip_prefix(dir) else {
+                    return true;
+                };
+                !e.file_type().is_dir()
+                    || !self.excludes(rel, &always)
+                    || always.iter().any(|a| a.starts_with(rel))
+                    || self.may_reinclude_below(&slash_path(rel))
+            })
+            .filter_map(Result::ok)
+            .filter(move |e| {
+                e.path()
+                    .strip_prefix(dir)
+                    .is_ok_and(|rel| !self.excludes(rel, &kept))
+            })
+    }
+
+    fn excludes(&self, rel: &Path, always: &[&Path]) -> bool {
+        !rel.as_os_str().is_empty() && !always.contains(&rel) && self.is_excluded(&slash_path(rel))
+    }
+
+    /// Whether a `/`-separated path relative to the context root is excluded.
+    /// As in Docker, a pattern matching a parent directory also matches its
+    /// contents, and the last matching pattern wins.
+    pub fn is_excluded(&self, rel: &str) -> bool {
+        let parts: Vec<&str> = rel.split('/').filter(|s| !s.is_empty()).collect();
+        let mut excluded = false;
+        for pattern in &self.patterns {
+            // Only an exclusion can flip an included path and vice versa
+            if pattern.negate != excluded {
+                continue;
+            }
+            if (1..=parts.len()).any(|n| match_segments(&pattern.segments, &parts[..n])) {
+                excluded = !pattern.negate;
+            }
+        }
+        excluded
+    }
+}
+
+// Whether `pattern` may match the directory `dir` or a path below it
+fn may_match_below(pattern: &[String], dir: &[&str]) -> bool {
+    match (pattern.split_first(), dir.split_first()) {
+        (None, _) | (_, None) => true,
+        (Some((first, _)), _) if first == "**" => true,
+        (Some((first, rest)), Some((seg, dir_rest))) => {
+            glob_match(first.as_bytes(), seg.as_bytes()) && may_match_below(rest, dir_rest)
+        }
+    }
+}
+
+fn match_segments(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|i| match_segments(rest, &path[i..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((seg, path_rest)) => {
+                glob_match(first.as_bytes(), seg.as_bytes()) && match_segments(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Match a single path segment against `*`, `?`, `[...]` and `\` escapes
+pub fn glob_match(pat: &[u8], text: &[u8]) -> bool {
+    match pat.first() {
+        None => text.is_empty(),
+        Some(b'*') => {
+            let rest = &pat[1..];
+            (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        Some(b'?') => !text.is_empty() && glob_match(&pat[1..], &text[1..]),
+        Some(b'[') => match (text.first(), class_match(&pat[1..], text.first().copied())) {
+            (Some(_), Some((true, len))) => glob_match(&pat[1 + len..], &text[1..]),
+            _ => false,
+        },
+        Some(b'\\') if pat.len() > 1 => {
+            text.first() == Some(&pat[1]) && glob_match(&pat[2..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && glob_match(&pat[1..], &text[1..]),
+    }
+}
+
+// Returns whether `c` matches the class and how many pattern bytes it used
+fn class_match(pat: &[u8], c: Option<u8>) -> Option<(bool, usize)> {
+    let c = c?;
+    let mut i = 0;
+    let negate = matches!(pat.first(), Some(b'^') | Some(b'!'));
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    while i < pat.len() {
+        if pat[i] == b']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        let mut lo = pat[i];
+        if lo == b'\\' && i + 1 < pat.len() {
+            i += 1;
+            lo = pat[i];
+        }
+        if i + 2 < pat.len() && pat[i + 1] == b'-' && pat[i + 2] != b']' {
+            let hi = pat[i + 2];
+            matched |= lo <= c && c <= hi;
+            i += 3;
+        } else {
+            matched |= lo == c;
+            i += 1;
+        }
+    }
+    // Unterminated class never matches
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn matches_docker_patterns() {
+        let ig = DockerIgnore::parse(
+            "# comment\n*.log\n/build\ndocs/**/*.md\n!docs/keep/README.md\nfile?.txt\n[a-c].bin\n",
+        );
+        assert!(ig.is_excluded("app.log"));
+        assert!(!ig.is_excluded("src/app.log"));
+        assert!(ig.is_excluded("build"));
+        assert!(ig.is_excluded("build/out/bin"));
+        assert!(ig.is_excluded("docs/a.md"));
+        assert!(ig.is_excluded("docs/x/y/z.md"));
+        assert!(!ig.is_excluded("docs/keep/README.md"));
+        assert!(ig.is_excluded("file1.txt"));
+        assert!(!ig.is_excluded("file10.txt"));
+        assert!(ig.is_excluded("b.bin"));
+        assert!(!ig.is_excluded("d.bin"));
+        assert!(!ig.is_excluded("src/main.rs"));
+    }
+
+    #[test]
+    fn last_match_wins_and_negation_reincludes() {
+        let ig = DockerIgnore::parse("*.md\n!README.md\nREADME*\n");
+        assert!(ig.is_excluded("CHANGES.md"));
+        assert!(ig.is_excluded("README.md"));
+
+        let ig = DockerIgnore::parse("**/target\n!crates/keep/target\n");
+        assert!(ig.is_excluded("target/debug/app"));
+        assert!(ig.is_excluded("crates/a/target"));
+        assert!(!ig.is_excluded("crates/keep/target/x"));
+        assert!(ig.may_reinclude_below("crates/keep"));
+        assert!(ig.may_reinclude_below("crates/keep/target"));
+        assert!(!ig.may_reinclude_below("crates/a/target"));
+        assert!(!ig.may_reinclude_below("web"));
+    }
+
+    #[test]
+    fn defaults_apply_and_can_be_overridden() {
+        let td = TempDir::new().unwrap();
+        let ig = DockerIgnore::open(td.path()).unwrap();
+        assert!(ig.is_excluded(".git/config"));
+        assert!(ig.is_excluded("web/node_modules/react/index.js"));
+        assert!(ig.is_excluded(".devenv/zed_ed25519"));
+        assert!(!ig.is_excluded(".devenv/Dockerfile"));
+        assert!(!ig.is_excluded("src/lib.rs"));
+
+        std::fs::write(td.path().join(FILENAME), "!node_modules\n").unwrap();
+        let ig = DockerIgnore::open(td.path()).unwrap();
+        assert!(!ig.is_excluded("node_modules/x"));
+        assert!(ig.is_excluded("web/node_modules/x"));
+    }
+}
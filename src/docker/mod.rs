@@ -1,32 +1,156 @@
 use std::{
-    collections::HashMap,
-    future,
+    collections::{BTreeMap, HashMap},
+    io::{self as stdio, BufWriter, Write},
     path::{Path, PathBuf},
 };
 
 use anyhow::{Result, anyhow, bail};
 use bollard::{
-    Docker, body_full,
+    Docker, body_try_stream,
     exec::{CreateExecOptions, ResizeExecOptions, StartExecOptions, StartExecResults},
-    models::{ContainerCreateBody, HostConfig, PortBinding},
+    models::{ContainerCreateBody, HostConfig, Mount, MountTypeEnum, PortBinding},
     query_parameters as qp,
 };
 use bytes::Bytes;
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
-use futures_util::StreamExt;
-use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
-use tracing::{debug, error};
-use walkdir::WalkDir;
+use futures_util::{Stream, StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{self, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    process::Command,
+    sync::mpsc,
+};
+use tracing::info;
+
+use self::{build::BuildMonitor, ignore::DockerIgnore, resources::ResourceLimits};
+use crate::util::slash_path;
 
+pub mod build;
+pub mod digest;
 pub mod file;
+pub mod ignore;
+pub mod resources;
+pub mod service;
+
+/// Size of the chunks the build context is streamed to Docker in
+const CONTEXT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Label holding a hash of the dev container's `Layout`
+const LABEL_LAYOUT: &str = "devenv.layout";
+
+/// Name given to a Dockerfile from outside the build context inside the context tar
+const EXTERNAL_DOCKERFILE: &str = ".devenv.Dockerfile";
 
 pub struct DockerClient(Docker);
 
+/// Options for `DockerClient::build_with_opts`
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    pub context_dir: PathBuf,
+    /// Path to the Dockerfile; may live outside `context_dir`
+    pub dockerfile: PathBuf,
+    pub tag: String,
+    /// Multi-stage build target
+    pub target: Option<String>,
+    /// Build arguments, already interpolated
+    pub build_args: BTreeMap<String, String>,
+    /// BuildKit secret ids mapped to host files
+    pub secrets: BTreeMap<String, PathBuf>,
+    pub pull: bool,
+    pub no_cache: bool,
+}
+
+impl BuildOptions {
+    /// The Dockerfile's path in the context, and its host path when it lives
+    /// outside. Like the Docker CLI, an outside Dockerfile is sent along.
+    fn context_dockerfile(&self) -> (PathBuf, Option<PathBuf>) {
+        match self.dockerfile.strip_prefix(&self.context_dir) {
+            Ok(rel) => (rel.to_path_buf(), None),
+            Err(_) => (
+                PathBuf::from(EXTERNAL_DOCKERFILE),
+                Some(self.dockerfile.clone()),
+            ),
+        }
+    }
+}
+
+/// Options for `DockerClient::run_detached`
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    pub container_name: String,
+    pub image: String,
+    /// Mounted at /workspace
+    pub project_dir: PathBuf,
+    /// Host port published for the container's SSH port
+    pub ssh_port: Option<u16>,
+    pub network: Option<String>,
+    /// `KEY=VALUE` pairs, already interpolated
+    pub env: Vec<String>,
+    pub layout: Layout,
+}
+
+/// Dev container options that can only be set when it is created
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Layout {
+    /// Published ports in `[[IP:]HOST:]CONTAINER[/PROTO]` syntax
+    pub ports: Vec<String>,
+    /// Extra mounts with resolved sources
+    pub mounts: Vec<MountOptions>,
+    pub resources: ResourceLimits,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MountOptions {
+    pub kind: MountKind,
+    /// Absolute host path or volume name; None for tmpfs and anonymous volumes
+    pub source: Option<String>,
+    pub target: String,
+    pub read_only: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MountKind {
+    #[default]
+    Bind,
+    /// Named volume, or an anonymous one without a source
+    Volume,
+    Tmpfs,
+}
+
+impl MountOptions {
+    fn to_mount(&self) -> Mount {
+        let typ = match self.kind {
+            MountKind::Bind => MountTypeEnum::BIND,
+            MountKind::Volume => MountTypeEnum::VOLUME,
+            MountKind::Tmpfs => MountTypeEnum::TMPFS,
+        };
+        Mount {
+            typ: Some(typ),
+            source: self.source.clone(),
+            target: Some(self.target.clone()),
+            read_only: self.read_only.then_some(true),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageItem {
+    pub tag: String,
+    /// Unix timestamp of image creation
+    pub created: i64,
+    pub size: i64,
+}
+
 #[derive(Debug, Clone)]
 pub struct PsItem {
     pub name: String,
     pub image: String,
     pub status: String,
+    /// Set for service containers: the environment and service name
+    pub service: Option<(String, String)>,
 }
 
 struct RawModeGuard;
@@ -37,43 +161,57 @@ impl DockerClient {
         Ok(Self(inner))
     }
 
-    pub async fn build_with_opts(
-        &self,
-        context_dir: &Path,
-        tag: &str,
-        pull: bool,
-        no_cache: bool,
-    ) -> Result<()> {
-        let tar = create_tar_from_dir(context_dir)?;
-        let opts = qp::BuildImageOptionsBuilder::default()
-            .dockerfile("Dockerfile")
-            .t(tag)
-            .pull(if pull { "true" } else { "false" })
-            .nocache(no_cache)
-            .rm(true)
-            .build();
-        let body = body_full(Bytes::from(tar));
-        let stream = self.0.build_image(opts, None, Some(body));
-        stream
-            .for_each(|msg| {
-                match msg {
-                    Ok(msg) => debug!("{msg:?}"),
-                    Err(e) => error!("{e:?}"),
-                }
+    pub async fn build_with_opts(&self, opts: &BuildOptions) -> Result<()> {
+        let (dockerfile, external) = opts.context_dockerfile();
+        let context = stream_context_tar(&opts.context_dir, &dockerfile, external)?;
+        let dockerfile = slash_path(&dockerfile);
 
-                future::ready(())
-            })
-            .await;
+        // The Engine API cannot serve BuildKit secrets, so hand those builds to the CLI
+        if !opts.secrets.is_empty() {
+            return build_with_cli(opts, &dockerfile, context).await;
+        }
+
+        let mut builder = qp::BuildImageOptionsBuilder::default()
+            .dockerfile(&dockerfile)
+            .t(&opts.tag)
+            .pull(if opts.pull { "true" } else { "false" })
+            .nocache(opts.no_cache)
+            .rm(true);
+        if let Some(target) = &opts.target {
+            builder = builder.target(target);
+        }
+        if !opts.build_args.is_empty() {
+            builder = builder.buildargs(&opts.build_args.clone().into_iter().collect());
+        }
+        let opts = builder.build();
+        let mut stream = self
+            .0
+            .build_image(opts, None, Some(body_try_stream(context)));
+        let mut monitor = BuildMonitor::new();
+        while let Some(msg) = stream.next().await {
+            match msg {
+                Ok(info) => monitor.handle(&info)?,
+                Err(bollard::errors::Error::DockerStreamError { error }) => {
+                    return Err(monitor.fail(error).into());
+                }
+                Err(e) => return Err(monitor.fail(e.to_string()).into()),
+            }
+        }
+        monitor.finish();
         Ok(())
     }
 
     pub async fn ps(&self) -> Result<Vec<PsItem>> {
         let mut filters: HashMap<String, Vec<String>> = HashMap::new();
         filters.insert("name".into(), vec!["devenv-".into()]);
+        self.list(false, filters).await
+    }
+
+    async fn list(&self, all: bool, filters: HashMap<String, Vec<String>>) -> Result<Vec<PsItem>> {
         let containers = self
             .0
             .list_containers(Some(qp::ListContainersOptions {
-                all: false,
+                all,
                 filters: Some(filters),
                 ..Default::default()
             }))
@@ -86,18 +224,69 @@ impl DockerClient {
                 .and_then(|v| v.first())
                 .map(|s| s.trim_start_matches('/').to_string())
                 .unwrap_or_default();
+            let labels = c.labels.unwrap_or_default();
+            let service = labels
+                .get(service::LABEL_ENV)
+                .zip(labels.get(service::LABEL_SERVICE))
+                .map(|(env, svc)| (env.clone(), svc.clone()));
             out.push(PsItem {
                 name,
                 image: c.image.unwrap_or_default(),
                 status: c.status.unwrap_or_default(),
+                service,
             });
         }
         Ok(out)
     }
 
+    pub async fn image_exists(&self, tag: &str) -> Result<bool> {
+        match self.0.inspect_image(tag).await {
+            Ok(_) => Ok(true),
+            Err(e) if is_not_found(&e) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// List tagged images of `repo`, newest first
+    pub async fn images(&self, repo: &str) -> Result<Vec<ImageItem>> {
+        let mut filters: HashMap<String, Vec<String>> = HashMap::new();
+        filters.insert("reference".into(), vec![repo.to_string()]);
+        let images = self
+            .0
+            .list_images(Some(qp::ListImagesOptions {
+                filters: Some(filters),
+                ..Default::default()
+            }))
+            .await?;
+        let mut out: Vec<ImageItem> = images
+            .into_iter()
+            .flat_map(|img| {
+                img.repo_tags
+                    .into_iter()
+                    .filter(|t| t.starts_with(&format!("{repo}:")))
+                    .map(move |tag| ImageItem {
+                        tag,
+                        created: img.created,
+                        size: img.size,
+                    })
+            })
+            .collect();
+        out.sort_by(|a, b| b.created.cmp(&a.created).then(a.tag.cmp(&b.tag)));
+        Ok(out)
+    }
+
+    /// Image reference a container was created from
+    pub async fn container_image(&self, name: &str) -> Result<Option<String>> {
+        let info = self
+            .0
+            .inspect_container(name, None::<qp::InspectContainerOptions>)
+            .await?;
+        Ok(info.config.and_then(|c| c.image))
+    }
+
     pub async fn container_exists(&self, name: &str) -> Result<bool> {
         let mut filters: HashMap<String, Vec<String>> = HashMap::new();
-        filters.insert("name".into(), vec![name.to_string()]);
+        filters.insert("name".into(), vec![exact_name(name)]);
         let containers = self
             .0
             .list_containers(Some(qp::ListContainersOptions {
@@ -111,7 +300,7 @@ impl DockerClient {
 
     pub async fn is_container_running(&self, name: &str) -> Result<bool> {
         let mut filters: HashMap<String, Vec<String>> = HashMap::new();
-        filters.insert("name".into(), vec![name.to_string()]);
+        filters.insert("name".into(), vec![exact_name(name)]);
         let containers = self
             .0
             .list_containers(Some(qp::ListContainersOptions {
@@ -150,16 +339,12 @@ impl DockerClient {
         Ok(())
     }
 
-    pub async fn run_detached(
-        &self,
-        container_name: &str,
-        image: &str,
-        project_dir: &Path,
-        host_ssh_port: Option<u16>,
-    ) -> Result<()> {
-        let binds = vec![format!("{}:/workspace", project_dir.display())];
+    /// Create and start the dev container
+    pub async fn run_detached(&self, opts: &RunOptions) -> Result<()> {
+        let binds = vec![format!("{}:/workspace", opts.project_dir.display())];
+        let mut exposed_ports = HashMap::new();
         let mut port_bindings: HashMap<String, Option<Vec<PortBinding>>> = HashMap::new();
-        if let Some(port) = host_ssh_port {
+        if let Some(port) = opts.ssh_port {
             port_bindings.insert(
                 "22/tcp".into(),
                 Some(vec![PortBinding {
@@ -168,25 +353,46 @@ impl DockerClient {
                 }]),
             );
         }
+        for spec in &opts.layout.ports {
+            let (port, binding) = service::parse_port(spec)?;
+            exposed_ports.insert(port.clone(), HashMap::new());
+            port_bindings
+                .entry(port)
+                .or_default()
+                .get_or_insert_with(Vec::new)
+                .push(binding);
+        }
 
-        let host_config = HostConfig {
+        let mounts = &opts.layout.mounts;
+        let mut host_config = HostConfig {
             binds: Some(binds),
+            mounts: (!mounts.is_empty())
+                .then(|| mounts.iter().map(MountOptions::to_mount).collect()),
             port_bindings: if port_bindings.is_empty() {
                 None
             } else {
                 Some(port_bindings)
             },
+            network_mode: opts.network.clone(),
             ..Default::default()
         };
 
+        opts.layout.resources.apply(&mut host_config);
+
         let config = ContainerCreateBody {
-            image: Some(image.to_string()),
+            image: Some(opts.image.clone()),
             cmd: Some(vec![
                 "/bin/sh".into(),
                 "-lc".into(),
                 "sleep infinity".into(),
             ]),
             working_dir: Some("/workspace".into()),
+            env: (!opts.env.is_empty()).then(|| opts.env.clone()),
+            exposed_ports: (!exposed_ports.is_empty()).then_some(exposed_ports),
+            labels: Some(HashMap::from([(
+                LABEL_LAYOUT.to_string(),
+                short_hash(&opts.layout),
+            )])),
             host_config: Some(host_config),
             ..Default::default()
         };
@@ -194,7 +400,7 @@ impl DockerClient {
         self.0
             .create_container(
                 Some(qp::CreateContainerOptions {
-                    name: Some(container_name.to_string()),
+                    name: Some(opts.container_name.clone()),
                     ..Default::default()
                 }),
                 config,
@@ -202,23 +408,80 @@ impl DockerClient {
             .await?;
 
         self.0
-            .start_container(container_name, None::<qp::StartContainerOptions>)
+            .start_container(&opts.container_name, None::<qp::StartContainerOptions>)
             .await?;
 
         Ok(())
     }
 
-    pub async fn exec_shell(&self, container_name: &str, script: &str) -> Result<()> {
+    /// Whether the container `name` was created with another layout
+    pub async fn layout_changed(&self, name: &str, layout: &Layout) -> Result<bool> {
+        let info = self
+            .0
+            .inspect_container(name, None::<qp::InspectContainerOptions>)
+            .await?;
+        // Containers from before the layout was tracked have the default one
+        let current = info
+            .config
+            .and_then(|c| c.labels)
+            .and_then(|l| l.get(LABEL_LAYOUT).cloned())
+            .unwrap_or_else(|| short_hash(&Layout::default()));
+        Ok(current != short_hash(layout))
+    }
+
+    /// Live port mappings of a container as `(CONTAINER/PROTO, IP:PORT)`,
+    /// ordered by container port
+    pub async fn published_ports(&self, name: &str) -> Result<Vec<(String, String)>> {
+        let info = self
+            .0
+            .inspect_container(name, None::<qp::InspectContainerOptions>)
+            .await?;
+        let ports = info
+            .network_settings
+            .and_then(|n| n.ports)
+            .unwrap_or_default();
+        let mut out: Vec<(String, String)> = ports
+            .into_iter()
+            .flat_map(|(port, bindings)| {
+                bindings.unwrap_or_default().into_iter().map(move |b| {
+                    let ip = b.host_ip.unwrap_or_default();
+                    let host = b.host_port.unwrap_or_default();
+                    let addr = if ip.contains(':') {
+                        format!("[{ip}]:{host}")
+                    } else {
+                        format!("{ip}:{host}")
+                    };
+                    (port.clone(), addr)
+                })
+            })
+            .collect();
+        out.sort_by_key(|(port, addr)| {
+            let (num, proto) = port.split_once('/').unwrap_or((port, ""));
+            (
+                num.parse::<u16>().unwrap_or(0),
+                proto.to_string(),
+                addr.clone(),
+            )
+        });
+        Ok(out)
+    }
+
+    pub async fn exec_shell(
+        &self,
+        container_name: &str,
+        script: &str,
+        env: &[String],
+    ) -> Result<()> {
         // Try bash first
         if self
-            .exec_and_wait(container_name, None, &["/bin/bash", "-lc", script])
+            .exec_and_wait(container_name, None, &["/bin/bash", "-lc", script], env)
             .await?
         {
             return Ok(());
         }
         // Fallback to sh
         if self
-            .exec_and_wait(container_name, None, &["/bin/sh", "-lc", script])
+            .exec_and_wait(container_name, None, &["/bin/sh", "-lc", script], env)
             .await?
         {
             return Ok(());
@@ -231,17 +494,23 @@ impl DockerClient {
         container_name: &str,
         user: &str,
         script: &str,
+        env: &[String],
     ) -> Result<()> {
         // Try bash first
         if self
-            .exec_and_wait(container_name, Some(user), &["/bin/bash", "-lc", script])
+            .exec_and_wait(
+                container_name,
+                Some(user),
+                &["/bin/bash", "-lc", script],
+                env,
+            )
             .await?
         {
             return Ok(());
         }
         // Fallback to sh
         if self
-            .exec_and_wait(container_name, Some(user), &["/bin/sh", "-lc", script])
+            .exec_and_wait(container_name, Some(user), &["/bin/sh", "-lc", script], env)
             .await?
         {
             return Ok(());
@@ -249,16 +518,16 @@ impl DockerClient {
         bail!("`docker exec -u` failed")
     }
 
-    pub async fn exec_interactive_shell(&self, container_name: &str) -> Result<()> {
+    pub async fn exec_interactive_shell(&self, container_name: &str, env: &[String]) -> Result<()> {
         if self
-            .exec_interactive(container_name, None, &["/bin/bash", "-l"])
+            .exec_interactive(container_name, None, &["/bin/bash", "-l"], env)
             .await?
         {
             return Ok(());
         }
         // Fallback to sh
         if self
-            .exec_interactive(container_name, None, &["/bin/sh", "-l"])
+            .exec_interactive(container_name, None, &["/bin/sh", "-l"], env)
             .await?
         {
             return Ok(());
@@ -271,6 +540,7 @@ impl DockerClient {
         container_name: &str,
         user: Option<&str>,
         cmd: &[&str],
+        env: &[String],
     ) -> Result<bool> {
         let exec = self
             .0
@@ -281,6 +551,7 @@ impl DockerClient {
                     attach_stderr: Some(true),
                     cmd: Some(cmd.iter().map(|s| s.to_string()).collect()),
                     user: user.map(|u| u.to_string()),
+                    env: (!env.is_empty()).then(|| env.to_vec()),
                     ..Default::default()
                 },
             )
@@ -326,6 +597,7 @@ impl DockerClient {
         container_name: &str,
         user: Option<&str>,
         cmd: &[&str],
+        env: &[String],
     ) -> Result<bool> {
         let _raw_mode = RawModeGuard::enable()?;
         let exec = self
@@ -339,6 +611,7 @@ impl DockerClient {
                     tty: Some(true),
                     cmd: Some(cmd.iter().map(|s| s.to_string()).collect()),
                     user: user.map(|u| u.to_string()),
+                    env: (!env.is_empty()).then(|| env.to_vec()),
                     ..Default::default()
                 },
             )
@@ -485,27 +758,198 @@ impl Drop for RawModeGuard {
     }
 }
 
-fn create_tar_from_dir(dir: &Path) -> Result<Vec<u8>> {
-    let mut ar = tar::Builder::new(Vec::<u8>::new());
-    let base = dir;
-    for entry in WalkDir::new(dir).into_iter().filter_map(Result::ok) {
+// Build with `docker build`, piping the context tar to its stdin so that
+// .dockerignore handling stays identical to API builds
+async fn build_with_cli(
+    opts: &BuildOptions,
+    dockerfile: &str,
+    context: impl Stream<Item = stdio::Result<Bytes>> + Send + 'static,
+) -> Result<()> {
+    for (id, path) in &opts.secrets {
+        if !path.is_file() {
+            bail!("Build secret '{id}' not found at {}", path.display());
+        }
+    }
+
+    let mut args: Vec<String> = vec![
+        "build".into(),
+        "--progress=plain".into(),
+        "-f".into(),
+        dockerfile.into(),
+        "-t".into(),
+        opts.tag.clone(),
+    ];
+    if let Some(target) = &opts.target {
+        args.extend(["--target".into(), target.clone()]);
+    }
+    if opts.pull {
+        args.push("--pull".into());
+    }
+    if opts.no_cache {
+        args.push("--no-cache".into());
+    }
+    for (key, value) in &opts.build_args {
+        args.extend(["--build-arg".into(), format!("{key}={value}")]);
+    }
+    for (id, path) in &opts.secrets {
+        args.extend(["--secret".into(), format!("id={id},src={}", path.display())]);
+    }
+    args.push("-".into());
+    // Don't log build arg values, they commonly carry credentials
+    info!(
+        "$ docker build --progress=plain -f {dockerfile} -t {} ... -",
+        opts.tag
+    );
+
+    let mut child = Command::new("docker")
+        .args(&args)
+        .env("DOCKER_BUILDKIT", "1")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Running `docker build`: {e}"))?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let feed = tokio::spawn(async move {
+        let mut context = Box::pin(context);
+        while let Some(chunk) = context.next().await {
+            stdin.write_all(&chunk?).await?;
+        }
+        stdin.shutdown().await
+    });
+
+    let mut monitor = BuildMonitor::new();
+    let mut lines = BufReader::new(child.stderr.take().expect("stderr is piped")).lines();
+    while let Some(line) = lines.next_line().await? {
+        monitor.handle_line(&line);
+    }
+    let status = child.wait().await?;
+    let fed = feed.await?;
+
+    if !status.success() {
+        let message = monitor
+            .last_error()
+            .map(String::from)
+            .unwrap_or_else(|| format!("`docker build` exited with {status}"));
+        return Err(monitor.fail(message).into());
+    }
+    if let Err(e) = fed {
+        return Err(monitor.fail(format!("Sending build context: {e}")).into());
+    }
+    monitor.finish();
+    Ok(())
+}
+
+// Stream the build context as a tar archive, built on a blocking thread so
+// that large projects are never held in memory at once
+fn stream_context_tar(
+    dir: &Path,
+    dockerfile: &Path,
+    external: Option<PathBuf>,
+) -> Result<impl Stream<Item = stdio::Result<Bytes>> + Send + 'static> {
+    let ignore = DockerIgnore::open(dir)?;
+    let (tx, mut rx) = mpsc::channel::<stdio::Result<Bytes>>(8);
+    let dir = dir.to_path_buf();
+    let dockerfile = dockerfile.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let writer = BufWriter::with_capacity(CONTEXT_CHUNK_SIZE, ChannelWriter(tx.clone()));
+        let res = write_context_tar(&dir, &ignore, &dockerfile, external.as_deref(), writer)
+            .and_then(|mut w| w.flush().map_err(Into::into));
+        if let Err(e) = res {
+            let _ = tx.blocking_send(Err(stdio::Error::other(e.to_string())));
+        }
+    });
+    Ok(stream::poll_fn(move |cx| rx.poll_recv(cx)))
+}
+
+struct ChannelWriter(mpsc::Sender<stdio::Result<Bytes>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> stdio::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| stdio::Error::from(stdio::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> stdio::Result<()> {
+        Ok(())
+    }
+}
+
+// Write `dir` to `writer` as a tar archive, skipping paths excluded by
+// `ignore`. Like the Docker CLI, the Dockerfile and .dockerignore are always
+// sent, even when ignored. An `external` Dockerfile is added as `dockerfile`.
+fn write_context_tar<W: Write>(
+    dir: &Path,
+    ignore: &DockerIgnore,
+    dockerfile: &Path,
+    external: Option<&Path>,
+    writer: W,
+) -> Result<W> {
+    let mut ar = tar::Builder::new(writer);
+    for entry in context_entries(dir, ignore, dockerfile) {
         let path = entry.path();
-        let rel = match path.strip_prefix(base) {
+        let rel = match path.strip_prefix(dir) {
             Ok(p) if p.as_os_str().is_empty() => PathBuf::from("."),
             Ok(p) => p.to_path_buf(),
             Err(_) => PathBuf::from("."),
         };
-        if rel.as_os_str().is_empty() {
-            continue;
-        }
         if path.is_dir() {
             ar.append_dir(rel, path)?;
         } else if path.is_file() {
             ar.append_path_with_name(path, rel)?;
         }
     }
-    let data = ar.into_inner()?;
-    Ok(data)
+    if let Some(path) = external {
+        ar.append_path_with_name(path, dockerfile)?;
+    }
+    Ok(ar.into_inner()?)
+}
+
+/// Entries of the build context at `dir` that are sent to Docker; the
+/// Dockerfile and .dockerignore are sent even when ignored
+fn context_entries<'a>(
+    dir: &'a Path,
+    ignore: &'a DockerIgnore,
+    dockerfile: &'a Path,
+) -> impl Iterator<Item = walkdir::DirEntry> + 'a {
+    ignore.walk(dir, vec![dockerfile, Path::new(".dockerignore")])
+}
+
+fn is_not_found(e: &bollard::errors::Error) -> bool {
+    matches!(
+        e,
+        bollard::errors::Error::DockerResponseServerError {
+            status_code: 404,
+            ..
+        }
+    )
+}
+
+// The `name` filter matches substrings, so `devenv-app` would also find `devenv-app-db`
+fn exact_name(name: &str) -> String {
+    let escaped: String = name
+        .chars()
+        .flat_map(|c| {
+            c.is_ascii_punctuation()
+                .then_some('\\')
+                .into_iter()
+                .chain([c])
+        })
+        .collect();
+    format!("^/{escaped}$")
+}
+
+/// Short hash of a value's JSON form, kept in a label to spot changed options
+fn short_hash(value: &impl Serialize) -> String {
+    let json = serde_json::to_vec(value).expect("options serialize to JSON");
+    Sha256::digest(json)
+        .iter()
+        .take(8)
+        .map(|b| format!("{b:02x}"))
+        .collect()
 }
 
 #[cfg(test)]
@@ -519,6 +963,17 @@ mod tests {
         s.strip_prefix("./").unwrap_or(&s).to_string()
     }
 
+    #[test]
+    fn short_hash_is_stable() {
+        // Changing it recreates every container, so pin the value
+        assert_eq!(short_hash(&Layout::default()), "11648ecc3ba2b222");
+        let layout = Layout {
+            ports: vec!["8080:80".into()],
+            ..Default::default()
+        };
+        assert_ne!(short_hash(&layout), short_hash(&Layout::default()));
+    }
+
     #[test]
     fn tar_includes_files_and_directories() {
         let td = TempDir::new().unwrap();
@@ -527,7 +982,99 @@ mod tests {
         std::fs::create_dir_all(root.join("sub")).unwrap();
         std::fs::write(root.join("sub/inner.txt"), "ok").unwrap();
 
-        let tar_bytes = create_tar_from_dir(root).unwrap();
+        let names = tar_names(root);
+        assert!(names.contains("."), "should contain base dir entry");
+        assert!(names.contains("root.txt"));
+        assert!(names.contains("sub"));
+        assert!(names.contains("sub/inner.txt"));
+    }
+
+    #[test]
+    fn tar_skips_ignored_paths_but_keeps_dockerfile() {
+        let td = TempDir::new().unwrap();
+        let root = td.path();
+        std::fs::write(root.join("Dockerfile"), "FROM scratch").unwrap();
+        std::fs::write(root.join(".dockerignore"), "Dockerfile\n*.log\n!keep.log\n").unwrap();
+        std::fs::write(root.join("a.log"), "").unwrap();
+        std::fs::write(root.join("keep.log"), "").unwrap();
+        std::fs::create_dir_all(root.join("target/debug")).unwrap();
+        std::fs::write(root.join("target/debug/app"), "bin").unwrap();
+        std::fs::create_dir_all(root.join("web/node_modules/x")).unwrap();
+        std::fs::write(root.join("web/node_modules/x/i.js"), "").unwrap();
+        std::fs::write(root.join("web/app.js"), "").unwrap();
+
+        let names = tar_names(root);
+        assert!(names.contains("Dockerfile"));
+        assert!(names.contains(".dockerignore"));
+        assert!(names.contains("keep.log"));
+        assert!(names.contains("web/app.js"));
+        assert!(!names.contains("a.log"));
+        assert!(!names.iter().any(|n| n.starts_with("target")));
+        assert!(!names.iter().any(|n| n.contains("node_modules")));
+    }
+
+    #[test]
+    fn tar_keeps_dockerfile_in_an_ignored_directory() {
+        let td = TempDir::new().unwrap();
+        let root = td.path();
+        std::fs::create_dir_all(root.join(".devenv")).unwrap();
+        std::fs::write(root.join(".devenv/Dockerfile"), "FROM scratch").unwrap();
+        std::fs::write(root.join(".devenv/zed_ed25519"), "key").unwrap();
+        std::fs::write(root.join(".devenv/notes"), "").unwrap();
+        std::fs::write(root.join(".dockerignore"), ".devenv\n").unwrap();
+
+        let ignore = DockerIgnore::open(root).unwrap();
+        let tar_bytes = write_context_tar(
+            root,
+            &ignore,
+            Path::new(".devenv/Dockerfile"),
+            None,
+            Vec::new(),
+        )
+        .unwrap();
+        let mut archive = tar::Archive::new(Cursor::new(tar_bytes));
+        let names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| norm(&e.unwrap().path().unwrap()))
+            .collect();
+        assert!(names.contains(&".devenv/Dockerfile".to_string()));
+        assert!(!names.iter().any(|n| n.contains("zed_ed25519")));
+        assert!(!names.contains(&".devenv/notes".to_string()));
+    }
+
+    #[test]
+    fn tar_includes_dockerfile_from_outside_context() {
+        let td = TempDir::new().unwrap();
+        let ctx = td.path().join("app");
+        std::fs::create_dir_all(&ctx).unwrap();
+        std::fs::write(ctx.join("main.rs"), "").unwrap();
+        let df = td.path().join("dev.Dockerfile");
+        std::fs::write(&df, "FROM scratch").unwrap();
+
+        let ignore = DockerIgnore::open(&ctx).unwrap();
+        let tar_bytes = write_context_tar(
+            &ctx,
+            &ignore,
+            Path::new(EXTERNAL_DOCKERFILE),
+            Some(&df),
+            Vec::new(),
+        )
+        .unwrap();
+        let mut archive = tar::Archive::new(Cursor::new(tar_bytes));
+        let names: Vec<String> = archive
+            .entries()
+            .unwrap()
+            .map(|e| norm(&e.unwrap().path().unwrap()))
+            .collect();
+        assert!(names.contains(&"main.rs".to_string()));
+        assert!(names.contains(&EXTERNAL_DOCKERFILE.to_string()));
+    }
+
+    fn tar_names(root: &Path) -> std::collections::HashSet<String> {
+        let ignore = DockerIgnore::open(root).unwrap();
+        let tar_bytes =
+            write_context_tar(root, &ignore, Path::new("Dockerfile"), None, Vec::new()).unwrap();
         assert!(!tar_bytes.is_empty());
 
         let mut archive = tar::Archive::new(Cursor::new(tar_bytes));
@@ -537,9 +1084,6 @@ mod tests {
             let path = e.path().unwrap();
             names.insert(norm(&path));
         }
-        assert!(names.contains("."), "should contain base dir entry");
-        assert!(names.contains("root.txt"));
-        assert!(names.contains("sub"));
-        assert!(names.contains("sub/inner.txt"));
+        names
     }
 }
@@ -0,0 +1,227 @@
+use anyhow::{Result, bail};
+use bollard::{
+    models::{ContainerStatsResponse, HostConfig, ResourcesUlimits},
+    query_parameters as qp,
+};
+use futures_util::StreamExt;
+use serde::Serialize;
+
+use super::DockerClient;
+
+/// Resource limits for the dev container, with sizes in bytes
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ResourceLimits {
+    /// CPU quota in units of 10^-9 CPUs
+    pub nano_cpus: Option<i64>,
+    pub memory: Option<i64>,
+    /// Memory plus swap; -1 for unlimited swap
+    pub memory_swap: Option<i64>,
+    pub pids_limit: Option<i64>,
+    pub shm_size: Option<i64>,
+    /// `(name, soft, hard)`
+    pub ulimits: Vec<(String, i64, i64)>,
+}
+
+impl ResourceLimits {
+    pub fn apply(&self, host_config: &mut HostConfig) {
+        host_config.nano_cpus = self.nano_cpus;
+        host_config.memory = self.memory;
+        host_config.memory_swap = self.memory_swap;
+        host_config.pids_limit = self.pids_limit;
+        host_config.shm_size = self.shm_size;
+        if !self.ulimits.is_empty() {
+            host_config.ulimits = Some(
+                self.ulimits
+                    .iter()
+                    .map(|(name, soft, hard)| ResourcesUlimits {
+                        name: Some(name.clone()),
+                        soft: Some(*soft),
+                        hard: Some(*hard),
+                    })
+                    .collect(),
+            );
+        }
+    }
+}
+
+/// A snapshot of a container's usage next to its limits
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Usage {
+    /// Percent of one CPU, so 2 busy CPUs are 200%
+    pub cpu_percent: f64,
+    /// Configured CPU limit, in CPUs
+    pub cpus: Option<f64>,
+    /// Memory in use, without the reclaimable page cache
+    pub memory: u64,
+    pub memory_limit: Option<u64>,
+    pub pids: u64,
+    pub pids_limit: Option<u64>,
+}
+
+impl DockerClient {
+    /// Sample the usage of a running container. Docker waits for a second
+    /// sample to compute CPU usage, so this takes about a second.
+    pub async fn usage(&self, name: &str) -> Result<Usage> {
+        let info = self
+            .0
+            .inspect_container(name, None::<qp::InspectContainerOptions>)
+            .await?;
+        let host_config = info.host_config.unwrap_or_default();
+        let mut stream = self.0.stats(
+            name,
+            Some(qp::StatsOptions {
+                stream: false,
+                one_shot: false,
+            }),
+        );
+        let Some(stats) = stream.next().await else {
+            bail!("Docker returned no stats for '{name}'");
+        };
+        let mut usage = usage_from(&stats?);
+        let positive = |v: Option<i64>| v.filter(|v| *v > 0);
+        usage.cpus = positive(host_config.nano_cpus).map(|n| n as f64 / 1e9);
+        usage.memory_limit = positive(host_config.memory).map(|m| m as u64);
+        usage.pids_limit = positive(host_config.pids_limit).map(|p| p as u64);
+        Ok(usage)
+    }
+}
+
+// CPU and memory are computed like `docker stats` does
+fn usage_from(stats: &ContainerStatsResponse) -> Usage {
+    let cpu = stats.cpu_stats.clone().unwrap_or_default();
+    let precpu = stats.precpu_stats.clone().unwrap_or_default();
+    let total = |s: &bollard::models::ContainerCpuStats| {
+        s.cpu_usage
+            .as_ref()
+            .and_then(|u| u.total_usage)
+            .unwrap_or(0)
+    };
+    let cpu_delta = total(&cpu).saturating_sub(total(&precpu)) as f64;
+    let system_delta = cpu
+        .system_cpu_usage
+        .unwrap_or(0)
+        .saturating_sub(precpu.system_cpu_usage.unwrap_or(0)) as f64;
+    let online = cpu.online_cpus.unwrap_or(1).max(1) as f64;
+    let cpu_percent = if system_delta > 0.0 {
+        cpu_delta / system_delta * online * 100.0
+    } else {
+        0.0
+    };
+
+    let memory = stats.memory_stats.clone().unwrap_or_default();
+    // cgroup v2 reports `inactive_file`, v1 `total_inactive_file`
+    let cache = memory
+        .stats
+        .as_ref()
+        .and_then(|s| {
+            s.get("inactive_file")
+                .or_else(|| s.get("total_inactive_file"))
+        })
+        .copied()
+        .unwrap_or(0);
+    Usage {
+        cpu_percent,
+        memory: memory.usage.unwrap_or(0).saturating_sub(cache),
+        pids: stats
+            .pids_stats
+            .as_ref()
+            .and_then(|p| p.current)
+            .unwrap_or(0),
+        ..Default::default()
+    }
+}
+
+/// Parse a size such as `512m`, `4g` or `1.5GiB` into bytes; units are binary
+pub fn parse_size(s: &str) -> Result<i64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let Ok(value) = number.parse::<f64>() else {
+        bail!("'{s}' is not a size");
+    };
+    let unit = unit.trim().to_ascii_lowercase();
+    let shift = match unit.trim_end_matches("ib").trim_end_matches('b') {
+        "" => 0,
+        "k" => 10,
+        "m" => 20,
+        "g" => 30,
+        "t" => 40,
+        _ => bail!("unknown unit in '{s}' (b, k, m, g or t)"),
+    };
+    Ok((value * (1u64 << shift) as f64) as i64)
+}
+
+/// Format bytes with a binary unit, e.g. `1.5GiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes}B")
+    } else {
+        format!("{value:.1}{}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bollard::models::{
+        ContainerCpuStats, ContainerCpuUsage, ContainerMemoryStats, ContainerPidsStats,
+    };
+
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("64k").unwrap(), 64 * 1024);
+        assert_eq!(parse_size("512m").unwrap(), 512 << 20);
+        assert_eq!(parse_size("4G").unwrap(), 4 << 30);
+        assert_eq!(parse_size("1.5GiB").unwrap(), 3 << 29);
+        assert_eq!(parse_size("2gb").unwrap(), 2 << 30);
+        for bad in ["", "g", "4 gigs", "-1"] {
+            assert!(parse_size(bad).is_err(), "{bad}");
+        }
+        assert_eq!(format_size(512), "512B");
+        assert_eq!(format_size(3 << 29), "1.5GiB");
+    }
+
+    #[test]
+    fn computes_usage_like_docker_stats() {
+        let cpu = |total, system| ContainerCpuStats {
+            cpu_usage: Some(ContainerCpuUsage {
+                total_usage: Some(total),
+                ..Default::default()
+            }),
+            system_cpu_usage: Some(system),
+            online_cpus: Some(4),
+            ..Default::default()
+        };
+        let stats = ContainerStatsResponse {
+            precpu_stats: Some(cpu(1_000, 10_000)),
+            cpu_stats: Some(cpu(3_000, 14_000)),
+            memory_stats: Some(ContainerMemoryStats {
+                usage: Some(300 << 20),
+                stats: Some(HashMap::from([("inactive_file".to_string(), 100 << 20)])),
+                ..Default::default()
+            }),
+            pids_stats: Some(ContainerPidsStats {
+                current: Some(42),
+                limit: None,
+            }),
+            ..Default::default()
+        };
+        let usage = usage_from(&stats);
+        assert_eq!(usage.cpu_percent, 200.0);
+        assert_eq!(usage.memory, 200 << 20);
+        assert_eq!(usage.pids, 42);
+    }
+}
@@ -0,0 +1,415 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use bollard::{
+    models::{
+        ContainerCreateBody, EndpointSettings, HealthConfig, HealthStatusEnum, HostConfig,
+        NetworkConnectRequest, NetworkCreateRequest, NetworkingConfig, PortBinding,
+    },
+    query_parameters as qp,
+};
+use futures_util::StreamExt;
+use serde::Serialize;
+use tracing::info;
+
+use super::{DockerClient, PsItem, is_not_found, short_hash};
+
+/// Label naming the environment a service container belongs to
+pub const LABEL_ENV: &str = "devenv.env";
+/// Label holding the service name
+pub const LABEL_SERVICE: &str = "devenv.service";
+/// Label holding a hash of the options the container was created with
+const LABEL_SPEC: &str = "devenv.spec";
+
+/// How often a starting service's health is polled
+const HEALTH_POLL: Duration = Duration::from_millis(500);
+
+/// Options for `DockerClient::start_service`, with env values and volume
+/// sources already resolved
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ServiceOptions {
+    pub env_name: String,
+    pub service: String,
+    pub container_name: String,
+    /// Network the service joins, with `service` as its hostname
+    pub network: String,
+    pub image: String,
+    pub env: BTreeMap<String, String>,
+    pub ports: Vec<String>,
+    pub volumes: Vec<String>,
+    pub healthcheck: Option<Healthcheck>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Healthcheck {
+    pub test: String,
+    pub interval: Option<Duration>,
+    pub timeout: Option<Duration>,
+    pub start_period: Option<Duration>,
+    pub retries: Option<u32>,
+}
+
+impl ServiceOptions {
+    fn create_body(&self) -> Result<ContainerCreateBody> {
+        let mut exposed_ports = HashMap::new();
+        let mut port_bindings: HashMap<String, Option<Vec<PortBinding>>> = HashMap::new();
+        for spec in &self.ports {
+            let (port, binding) = parse_port(spec)
+                .with_context(|| format!("Service '{}': invalid port '{spec}'", self.service))?;
+            exposed_ports.insert(port.clone(), HashMap::new());
+            port_bindings
+                .entry(port)
+                .or_default()
+                .get_or_insert_with(Vec::new)
+                .push(binding);
+        }
+
+        let labels = HashMap::from([
+            (LABEL_ENV.to_string(), self.env_name.clone()),
+            (LABEL_SERVICE.to_string(), self.service.clone()),
+            (LABEL_SPEC.to_string(), short_hash(self)),
+        ]);
+        let endpoint = EndpointSettings {
+            aliases: Some(vec![self.service.clone()]),
+            ..Default::default()
+        };
+
+        Ok(ContainerCreateBody {
+            image: Some(self.image.clone()),
+            env: Some(self.env.iter().map(|(k, v)| format!("{k}={v}")).collect()),
+            exposed_ports: Some(exposed_ports),
+            healthcheck: self.healthcheck.as_ref().map(Healthcheck::to_config),
+            labels: Some(labels),
+            host_config: Some(HostConfig {
+                binds: Some(self.volumes.clone()),
+                port_bindings: Some(port_bindings),
+                network_mode: Some(self.network.clone()),
+                ..Default::default()
+            }),
+            networking_config: Some(NetworkingConfig {
+                endpoints_config: Some(HashMap::from([(self.network.clone(), endpoint)])),
+            }),
+            ..Default::default()
+        })
+    }
+}
+
+impl Healthcheck {
+    fn to_config(&self) -> HealthConfig {
+        let nanos = |d: Option<Duration>| d.map(|d| d.as_nanos() as i64);
+        HealthConfig {
+            test: Some(vec!["CMD-SHELL".into(), self.test.clone()]),
+            interval: nanos(self.interval),
+            timeout: nanos(self.timeout),
+            start_period: nanos(self.start_period),
+            retries: self.retries.map(i64::from),
+            ..Default::default()
+        }
+    }
+}
+
+impl DockerClient {
+    /// Create the bridge network `name` unless it exists
+    pub async fn ensure_network(&self, name: &str) -> Result<()> {
+        match self
+            .0
+            .inspect_network(name, None::<qp::InspectNetworkOptions>)
+            .await
+        {
+            Ok(_) => return Ok(()),
+            Err(e) if is_not_found(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+        self.0
+            .create_network(NetworkCreateRequest {
+                name: name.to_string(),
+                driver: Some("bridge".into()),
+                ..Default::default()
+            })
+            .await?;
+        info!("Created network '{name}'");
+        Ok(())
+    }
+
+    /// Remove the network `name`; returns false if it did not exist
+    pub async fn remove_network(&self, name: &str) -> Result<bool> {
+        match self.0.remove_network(name).await {
+            Ok(()) => Ok(true),
+            Err(e) if is_not_found(&e) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Attach an existing container to `network` unless it is already attached
+    pub async fn connect_network(&self, network: &str, container: &str) -> Result<()> {
+        let info = self
+            .0
+            .inspect_container(container, None::<qp::InspectContainerOptions>)
+            .await?;
+        let attached = info
+            .network_settings
+            .and_then(|n| n.networks)
+            .is_some_and(|n| n.contains_key(network));
+        if !attached {
+            self.0
+                .connect_network(
+                    network,
+                    NetworkConnectRequest {
+                        container: Some(container.to_string()),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Pull `image` unless it is present locally
+    pub async fn pull_if_missing(&self, image: &str) -> Result<()> {
+        if self.image_exists(image).await? {
+            return Ok(());
+        }
+        info!("Pulling '{image}'...");
+        let (repo, tag) = split_reference(image);
+        let opts = qp::CreateImageOptions {
+            from_image: Some(repo.to_string()),
+            tag: Some(tag.to_string()),
+            ..Default::default()
+        };
+        let mut stream = self.0.create_image(Some(opts), None, None);
+        while let Some(msg) = stream.next().await {
+            msg.with_context(|| format!("Pulling '{image}'"))?;
+        }
+        Ok(())
+    }
+
+    /// Start the service container, creating it if needed. A container created
+    /// from different options is replaced; named volumes are kept.
+    pub async fn start_service(&self, opts: &ServiceOptions) -> Result<()> {
+        let name = &opts.container_name;
+        if self.container_exists(name).await? {
+            let info = self
+                .0
+                .inspect_container(name, None::<qp::InspectContainerOptions>)
+                .await?;
+            let labels = info.config.and_then(|c| c.labels).unwrap_or_default();
+            // Another environment's dev container may hold the same name
+            if labels.get(LABEL_ENV) != Some(&opts.env_name)
+                || labels.get(LABEL_SERVICE) != Some(&opts.service)
+            {
+                bail!(
+                    "Container '{name}' already exists and is not service '{}' of environment '{}'",
+                    opts.service,
+                    opts.env_name
+                );
+            }
+            if labels.get(LABEL_SPEC) == Some(&short_hash(opts)) {
+                if !self.is_container_running(name).await? {
+                    self.start(name).await?;
+                    info!("Started service '{}'", opts.service);
+                }
+                return Ok(());
+            }
+            info!("Service '{}' changed; recreating '{name}'", opts.service);
+            self.remove_container(name, true).await?;
+        }
+
+        let body = opts.create_body()?;
+        self.pull_if_missing(&opts.image).await?;
+        self.0
+            .create_container(
+                Some(qp::CreateContainerOptions {
+                    name: Some(name.clone()),
+                    ..Default::default()
+                }),
+                body,
+            )
+            .await?;
+        self.start(name).await?;
+        info!("Started service '{}'", opts.service);
+        Ok(())
+    }
+
+    /// Whether the dev container `name` exists. Fails when it is a service
+    /// container, which `devenv-{env}-{service}` names can collide with.
+    pub async fn dev_container_exists(&self, name: &str) -> Result<bool> {
+        let info = match self
+            .0
+            .inspect_container(name, None::<qp::InspectContainerOptions>)
+            .await
+        {
+            Ok(info) => info,
+            Err(e) if is_not_found(&e) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let labels = info.config.and_then(|c| c.labels).unwrap_or_default();
+        if let Some((env, svc)) = labels.get(LABEL_ENV).zip(labels.get(LABEL_SERVICE)) {
+            bail!(
+                "Container '{name}' is service '{svc}' of environment '{env}', not a dev container"
+            );
+        }
+        Ok(true)
+    }
+
+    /// Wait until the container's healthcheck passes; returns at once for
+    /// containers without one
+    pub async fn wait_healthy(&self, name: &str) -> Result<()> {
+        loop {
+            let info = self
+                .0
+                .inspect_container(name, None::<qp::InspectContainerOptions>)
+                .await?;
+            let state = info.state.unwrap_or_default();
+            let health = state.health.unwrap_or_default();
+            match health.status {
+                None | Some(HealthStatusEnum::NONE | HealthStatusEnum::EMPTY) => return Ok(()),
+                Some(HealthStatusEnum::HEALTHY) => return Ok(()),
+                Some(HealthStatusEnum::UNHEALTHY) => {
+                    let output = health
+                        .log
+                        .and_then(|l| l.last().and_then(|r| r.output.clone()))
+                        .unwrap_or_default();
+                    bail!("'{name}' is unhealthy: {}", output.trim());
+                }
+                Some(HealthStatusEnum::STARTING) if state.running != Some(true) => {
+                    bail!("'{name}' exited before becoming healthy");
+                }
+                Some(HealthStatusEnum::STARTING) => tokio::time::sleep(HEALTH_POLL).await,
+            }
+        }
+    }
+
+    /// Service containers of the environment `env_name`, running or not
+    pub async fn service_containers(&self, env_name: &str) -> Result<Vec<PsItem>> {
+        let mut filters: HashMap<String, Vec<String>> = HashMap::new();
+        filters.insert("label".into(), vec![format!("{LABEL_ENV}={env_name}")]);
+        self.list(true, filters).await
+    }
+}
+
+/// Parse a `[[IP:]HOST:]CONTAINER[/PROTO]` port mapping into the container
+/// port key and its host binding
+pub fn parse_port(spec: &str) -> Result<(String, PortBinding)> {
+    let (ports, proto) = spec.rsplit_once('/').unwrap_or((spec, "tcp"));
+    if !matches!(proto, "tcp" | "udp") {
+        bail!("unknown protocol '{proto}'");
+    }
+    let parts: Vec<&str> = ports.split(':').collect();
+    let (ip, host, container) = match parts[..] {
+        [container] => (None, None, container),
+        [host, container] => (None, Some(host), container),
+        // `IP::CONTAINER` binds a free port on `IP`
+        [ip, "", container] => (Some(ip), None, container),
+        [ip, host, container] => (Some(ip), Some(host), container),
+        _ => bail!("expected [[IP:]HOST:]CONTAINER"),
+    };
+    for port in host.into_iter().chain([container]) {
+        port.parse::<u16>()
+            .map_err(|_| anyhow::anyhow!("'{port}' is not a port number"))?;
+    }
+    Ok((
+        format!("{container}/{proto}"),
+        PortBinding {
+            host_ip: ip.map(String::from),
+            host_port: host.map(String::from),
+        },
+    ))
+}
+
+/// Parse a duration such as `5s`, `1m30s` or `500ms`
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let mut total = Duration::ZERO;
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        bail!("empty duration");
+    }
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .with_context(|| format!("'{s}' is missing a unit (h, m, s or ms)"))?;
+        let value: u64 = rest[..digits]
+            .parse()
+            .with_context(|| format!("invalid duration '{s}'"))?;
+        rest = &rest[digits..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        total += match &rest[..unit_len] {
+            "h" => Duration::from_secs(value * 3600),
+            "m" => Duration::from_secs(value * 60),
+            "s" => Duration::from_secs(value),
+            "ms" => Duration::from_millis(value),
+            unit => bail!("unknown unit '{unit}' in duration '{s}'"),
+        };
+        rest = &rest[unit_len..];
+    }
+    Ok(total)
+}
+
+// `postgres:16` → (`postgres`, `16`); digests stay in the repo part
+fn split_reference(image: &str) -> (&str, &str) {
+    if image.contains('@') {
+        return (image, "");
+    }
+    match image.rsplit_once(':') {
+        Some((repo, tag)) if !tag.contains('/') => (repo, tag),
+        _ => (image, "latest"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_port_mappings() {
+        let (port, binding) = parse_port("5432").unwrap();
+        assert_eq!(port, "5432/tcp");
+        assert_eq!(binding.host_port, None);
+
+        let (port, binding) = parse_port("127.0.0.1:16379:6379/udp").unwrap();
+        assert_eq!(port, "6379/udp");
+        assert_eq!(binding.host_ip.as_deref(), Some("127.0.0.1"));
+        assert_eq!(binding.host_port.as_deref(), Some("16379"));
+
+        for bad in ["pg", "5432:pg", "1:2:3:4", "5432/sctp", "70000"] {
+            assert!(parse_port(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("5s").unwrap(), Duration::from_secs(5));
+        assert_eq!(parse_duration("1m30s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        for bad in ["", "5", "s", "5 sec", "1d"] {
+            assert!(parse_duration(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn splits_image_references() {
+        assert_eq!(split_reference("postgres:16"), ("postgres", "16"));
+        assert_eq!(split_reference("redis"), ("redis", "latest"));
+        assert_eq!(
+            split_reference("localhost:5000/db"),
+            ("localhost:5000/db", "latest")
+        );
+    }
+
+    #[test]
+    fn spec_hash_tracks_options() {
+        let opts = ServiceOptions {
+            service: "db".into(),
+            image: "postgres:16".into(),
+            ..Default::default()
+        };
+        let mut changed = opts.clone();
+        changed.env.insert("POSTGRES_PASSWORD".into(), "dev".into());
+        assert_eq!(short_hash(&opts), short_hash(&opts.clone()));
+        assert_ne!(short_hash(&opts), short_hash(&changed));
+    }
+}
@@ -0,0 +1,198 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+
+use crate::interpolate::interpolate_with;
+
+/// Read a `.env` file, returning its variables in file order
+pub fn load(path: &Path) -> Result<Vec<(String, String)>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+    parse(&contents, |name| std::env::var(name).ok())
+        .with_context(|| format!("Parsing {}", path.display()))
+}
+
+/// Parse dotenv `KEY=VALUE` lines. `export` prefixes and `#` comments are
+/// allowed. Single-quoted values are literal; double-quoted values may span
+/// lines and understand `\n`, `\t`, `\"` and `\$`. Unquoted and double-quoted
+/// values are interpolated from earlier variables in the file, then from
+/// `host`. A bare `KEY` passes the host value through when it is set.
+pub fn parse(
+    contents: &str,
+    host: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<(String, String)>> {
+    let mut vars: Vec<(String, String)> = Vec::new();
+    let lookup = |vars: &[(String, String)], name: &str| {
+        vars.iter()
+            .rev()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+            .or_else(|| host(name))
+    };
+
+    let mut lines = contents.lines().enumerate();
+    while let Some((no, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+        let Some((key, value)) = line.split_once('=') else {
+            if !valid_key(line) {
+                bail!("line {}: expected KEY=VALUE", no + 1);
+            }
+            if let Some(value) = host(line) {
+                vars.push((line.to_string(), value));
+            }
+            continue;
+        };
+        let key = key.trim();
+        if !valid_key(key) {
+            bail!("line {}: invalid variable name '{key}'", no + 1);
+        }
+
+        let value = value.trim_start();
+        let (raw, interpolated) = match value.chars().next() {
+            Some(q @ ('\'' | '"')) => {
+                // Collect lines until the closing quote
+                let mut text = value[1..].to_string();
+                let end = loop {
+                    if let Some(end) = closing_quote(&text, q) {
+                        break end;
+                    }
+                    let Some((_, next)) = lines.next() else {
+                        bail!("line {}: unterminated {q}quoted value", no + 1);
+                    };
+                    text.push('\n');
+                    text.push_str(next);
+                };
+                let rest = text[end + 1..].trim();
+                if !(rest.is_empty() || rest.starts_with('#')) {
+                    bail!("line {}: unexpected text after quoted value", no + 1);
+                }
+                let text = &text[..end];
+                if q == '\'' {
+                    (text.to_string(), false)
+                } else {
+                    (unescape(text), true)
+                }
+            }
+            _ => {
+                let text = match value.find(" #") {
+                    Some(i) => &value[..i],
+                    None => value,
+                };
+                (text.trim_end().to_string(), true)
+            }
+        };
+        let value = if interpolated {
+            interpolate_with(&raw, |name| lookup(&vars, name))
+                .with_context(|| format!("line {}", no + 1))?
+        } else {
+            raw
+        };
+        vars.push((key.to_string(), value));
+    }
+    Ok(vars)
+}
+
+fn valid_key(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+// Index of the unescaped closing quote
+fn closing_quote(text: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote == '"' => escaped = true,
+            c if c == quote => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+// `\$` becomes `$$` so interpolation leaves a literal `$`
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('$') => out.push_str("$$"),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/me".into()),
+            "TOKEN" => Some("s3cret".into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_dotenv_syntax() {
+        let env = parse(
+            r#"
+# database
+export DB_HOST=localhost
+DB_PORT = 5432   # inline comment
+DB_URL="postgres://${DB_HOST}:${DB_PORT}/app"
+LITERAL='${DB_HOST} \n'
+ESCAPED="a\tb \$HOME \"q\""
+CACHE=$HOME/.cache
+MULTI="line one
+line two"
+EMPTY=
+TOKEN
+MISSING
+HASH=a#b
+"#,
+            host,
+        )
+        .unwrap();
+        let get = |k: &str| {
+            env.iter()
+                .find(|(key, _)| key == k)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(get("DB_HOST"), Some("localhost"));
+        assert_eq!(get("DB_PORT"), Some("5432"));
+        assert_eq!(get("DB_URL"), Some("postgres://localhost:5432/app"));
+        assert_eq!(get("LITERAL"), Some("${DB_HOST} \\n"));
+        assert_eq!(get("ESCAPED"), Some("a\tb $HOME \"q\""));
+        assert_eq!(get("CACHE"), Some("/home/me/.cache"));
+        assert_eq!(get("MULTI"), Some("line one\nline two"));
+        assert_eq!(get("EMPTY"), Some(""));
+        assert_eq!(get("TOKEN"), Some("s3cret"));
+        assert_eq!(get("MISSING"), None);
+        assert_eq!(get("HASH"), Some("a#b"));
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for bad in ["1KEY=x", "KEY=\"open", "KEY='a' b", "not a var"] {
+            assert!(parse(bad, host).is_err(), "{bad}");
+        }
+    }
+}
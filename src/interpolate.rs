@@ -0,0 +1,165 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+use tracing::warn;
+
+/// Expand `$VAR`, `${VAR}`, `${VAR:-default}`, `${VAR-default}` and
+/// `${VAR:?message}` from the host environment. `$$` is a literal `$`.
+/// Unset variables without a default expand to an empty string.
+pub fn interpolate(input: &str) -> Result<String> {
+    interpolate_with(input, |name| std::env::var(name).ok())
+}
+
+/// Interpolate a host path, expanding a leading `~` and resolving relative
+/// paths against `base`
+pub fn expand_path(input: &str, base: &Path) -> Result<PathBuf> {
+    let expanded = interpolate(input)?;
+    let path = match expanded.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            let Some(home) = dirs::home_dir() else {
+                bail!("Cannot expand '~' in '{input}': no home directory");
+            };
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(expanded),
+    };
+    Ok(if path.is_relative() {
+        base.join(path)
+    } else {
+        path
+    })
+}
+
+/// Interpolate `input`, resolving variables with `lookup` instead of the host environment
+pub fn interpolate_with(input: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    substitute(input, |name, op| expand(name, op, &lookup))
+}
+
+/// Replace each variable reference in `input` with what `replace` returns for
+/// its name and operator (`""`, `:-default`, `-default` or `:?message`)
+pub fn substitute(
+    input: &str,
+    mut replace: impl FnMut(&str, &str) -> Result<String>,
+) -> Result<String> {
+    let mut out = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            out.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('$') => {
+                chars.next();
+                out.push('$');
+            }
+            Some('{') => {
+                chars.next();
+                let mut expr = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => expr.push(c),
+                        None => bail!("Unterminated '${{' in '{input}'"),
+                    }
+                }
+                let end = expr
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(expr.len());
+                let (name, op) = expr.split_at(end);
+                if name.is_empty() {
+                    bail!("Invalid variable reference '${{{expr}}}'");
+                }
+                out.push_str(&replace(name, op)?);
+            }
+            Some(c) if c.is_ascii_alphabetic() || *c == '_' => {
+                let mut name = String::new();
+                while let Some(c) = chars
+                    .peek()
+                    .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+                {
+                    name.push(*c);
+                    chars.next();
+                }
+                out.push_str(&replace(&name, "")?);
+            }
+            _ => out.push('$'),
+        }
+    }
+    Ok(out)
+}
+
+fn expand(name: &str, op: &str, lookup: &impl Fn(&str) -> Option<String>) -> Result<String> {
+    let value = lookup(name);
+    Ok(match op {
+        "" => value.unwrap_or_else(|| {
+            warn!("Warning: environment variable '{name}' is not set; using an empty string");
+            String::new()
+        }),
+        _ if op.starts_with(":-") => value.filter(|v| !v.is_empty()).unwrap_or(op[2..].into()),
+        _ if op.starts_with('-') => value.unwrap_or(op[1..].into()),
+        _ if op.starts_with(":?") => match value.filter(|v| !v.is_empty()) {
+            Some(v) => v,
+            None => bail!("{name}: {}", &op[2..]),
+        },
+        _ => bail!("Unsupported variable expression '${{{name}{op}}}'"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "USER" => Some("alice".into()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expands_variables_and_defaults() {
+        let cases = [
+            ("plain", "plain"),
+            ("$USER", "alice"),
+            ("${USER}-x", "alice-x"),
+            ("a/$USER/b", "a/alice/b"),
+            ("${MISSING}", ""),
+            ("${MISSING:-dflt}", "dflt"),
+            ("${EMPTY:-dflt}", "dflt"),
+            ("${EMPTY-dflt}", ""),
+            ("${MISSING-dflt}", "dflt"),
+            ("$$USER", "$USER"),
+            ("cost: 5$", "cost: 5$"),
+        ];
+        for (input, want) in cases {
+            assert_eq!(interpolate_with(input, lookup).unwrap(), want, "{input}");
+        }
+    }
+
+    #[test]
+    fn reports_errors() {
+        let err = interpolate_with("${TOKEN:?must be set}", lookup).unwrap_err();
+        assert_eq!(err.to_string(), "TOKEN: must be set");
+        assert!(interpolate_with("${USER", lookup).is_err());
+        assert!(interpolate_with("${USER:+x}", lookup).is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn expands_paths() {
+        let base = Path::new("/project");
+        assert_eq!(
+            expand_path("secrets/token", base).unwrap(),
+            PathBuf::from("/project/secrets/token")
+        );
+        assert_eq!(
+            expand_path("/etc/token", base).unwrap(),
+            PathBuf::from("/etc/token")
+        );
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(expand_path("~/.npmrc", base).unwrap(), home.join(".npmrc"));
+        }
+    }
+}
@@ -1,24 +1,44 @@
-use std::{fs, path::PathBuf, process::Command};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{IsTerminal, Write},
+    path::PathBuf,
+    process::Command,
+};
 
-use anyhow::{Result, anyhow, bail};
+use anyhow::{Context, Result, anyhow, bail};
 use clap::Parser;
-use tracing::{Level, info, warn};
+use tracing::{Level, debug, info, warn};
 use tracing_subscriber::EnvFilter;
 
 use crate::{
-    cli::{Cli, Commands},
-    config::Config,
+    cli::{Cli, Commands, ConfigCommand, DockerfileCommand, ExportCommand},
+    config::{
+        Config, Service, layers,
+        validate::{self, validate},
+    },
+    detect::Detector,
     docker::{
-        DockerClient,
-        file::{Dockerfile, OsFamily},
+        BuildOptions, DockerClient, Layout, MountKind, MountOptions, PsItem, RunOptions,
+        digest::{TAG_LEN, image_digest},
+        file::Dockerfile,
+        resources::{ResourceLimits, Usage, format_size, parse_size},
+        service::{Healthcheck, ServiceOptions, parse_duration},
     },
+    interpolate::{expand_path, interpolate},
 };
 
 mod cli;
+mod compose;
 mod config;
 mod detect;
+mod devcontainer;
+mod diff;
 mod docker;
+mod dotenv;
+mod interpolate;
 mod registry;
+mod util;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -40,7 +60,12 @@ async fn main() -> Result<()> {
         .map_err(|e| anyhow!(e))?;
 
     return match cli.command {
-        Commands::Init { path } => cmd_init(path).await,
+        Commands::Init {
+            path,
+            depth,
+            from_devcontainer,
+            compose,
+        } => cmd_init(path, depth, from_devcontainer, compose).await,
         Commands::List => cmd_list().await,
         Commands::Start(args) => {
             cmd_start(
@@ -55,6 +80,8 @@ async fn main() -> Result<()> {
         Commands::Stop { name } => cmd_stop(name.as_deref()).await,
         Commands::Remove { name } => cmd_remove(name.as_deref()).await,
         Commands::Attach { name } => cmd_attach(name.as_deref()).await,
+        Commands::Ports { name } => cmd_ports(name.as_deref()).await,
+        Commands::Stats { name } => cmd_stats(name.as_deref()).await,
         Commands::Restart(args) => {
             cmd_restart(
                 args.name.as_deref(),
@@ -66,34 +93,80 @@ async fn main() -> Result<()> {
             .await
         }
         Commands::Build(args) => cmd_build(args.name.as_deref(), args.rebuild, args.pull).await,
+        Commands::Images { name } => cmd_images(name.as_deref()).await,
+        Commands::Detect(args) => cmd_detect(args.path, args.depth, args.explain, args.json),
+        Commands::Export {
+            command: ExportCommand::Devcontainer(args),
+        } => cmd_export_devcontainer(args.name.as_deref(), args.force),
+        Commands::Dockerfile {
+            command: DockerfileCommand::Diff(args),
+        } => cmd_dockerfile_diff(args.name.as_deref(), args.check),
+        Commands::Config {
+            command: ConfigCommand::Validate(args),
+        } => cmd_config_validate(args.name.as_deref()),
+        Commands::Config {
+            command: ConfigCommand::Show(args),
+        } => cmd_config_show(args.name.as_deref(), args.resolved),
     };
 }
 
-async fn cmd_init(path: Option<PathBuf>) -> Result<()> {
+async fn cmd_init(
+    path: Option<PathBuf>,
+    depth: usize,
+    from_devcontainer: bool,
+    compose: bool,
+) -> Result<()> {
     let project_dir = path.unwrap_or_else(|| std::env::current_dir().unwrap());
     if !project_dir.exists() {
         bail!("Path does not exist: {}", project_dir.display());
     }
 
     // Create devenv.toml
-    let cfg = if Config::exists(&project_dir) {
+    let created = !Config::exists(&project_dir);
+    let mut cfg = if !created {
+        if from_devcontainer {
+            bail!(
+                "{} already has a devenv.toml; remove it to import devcontainer.json",
+                project_dir.display()
+            );
+        }
         let cfg = Config::open(&project_dir)?;
         info!("Using existing {}", cfg.path.display());
         cfg
+    } else if from_devcontainer {
+        let mut warnings = Vec::new();
+        let cfg = Config::create_with(&project_dir, |devenv| {
+            warnings = devcontainer::import(&project_dir, devenv)?;
+            Ok(())
+        })?;
+        info!("Created {} from devcontainer.json", cfg.path.display());
+        for warning in warnings {
+            warn!("Warning: {warning}");
+        }
+        cfg
     } else {
-        let cfg = Config::create(&project_dir)?;
+        let cfg = Config::create(&project_dir, &Detector::load(depth)?)?;
         info!("Created {}", cfg.path.display());
         cfg
     };
+    if compose {
+        import_compose(&mut cfg, true)?;
+    } else if created && let Err(e) = import_compose(&mut cfg, false) {
+        // devenv.toml is already written, so finish init without the services
+        warn!("Warning: not importing compose services: {e:#}");
+    }
 
     // Create Dockerfile
-    if Dockerfile::exists(&project_dir) {
-        info!("Found existing Dockerfile; leaving it unchanged");
+    let dockerfile_path = cfg.dockerfile_path();
+    if Dockerfile::exists(&dockerfile_path) {
+        info!(
+            "Found existing {}; leaving it unchanged",
+            dockerfile_path.display()
+        );
     } else {
-        let dockerfile =
-            Dockerfile::create(&cfg.devenv.image, &cfg.devenv.packages, OsFamily::Debian)?;
-        dockerfile.write(&project_dir)?;
-        info!("Created Dockerfile in {}", project_dir.display());
+        let dockerfile = Dockerfile::create(&cfg.devenv, &project_dir)?;
+        dockerfile.write(&dockerfile_path)?;
+        info!("Created {}", dockerfile_path.display());
     }
 
     // Register environment in global registry
@@ -105,33 +178,119 @@ async fn cmd_init(path: Option<PathBuf>) -> Result<()> {
     );
 
     // Build image now
-    let image_tag = format!("devenv-{}:latest", cfg.devenv.name);
+    let opts = build_options(&cfg, false, false)?;
     info!(
         "Building image '{}' (FROM {})...",
-        image_tag, cfg.devenv.image
+        opts.tag, cfg.devenv.image
     );
     let docker = DockerClient::new()?;
-    docker
-        .build_with_opts(&project_dir, &image_tag, false, false)
-        .await?;
-    info!("Image built: {image_tag}");
+    docker.build_with_opts(&opts).await?;
+    info!("Image built: {}", opts.tag);
+
+    Ok(())
+}
+
+/// Offer to add the services of the project's compose file to the config;
+/// `assume_yes` imports them without asking
+fn import_compose(cfg: &mut Config, assume_yes: bool) -> Result<()> {
+    let Some(path) = compose::find(cfg.project_dir()) else {
+        if assume_yes {
+            bail!("No compose file found in {}", cfg.project_dir().display());
+        }
+        return Ok(());
+    };
+    if !cfg.devenv.services.is_empty() {
+        if assume_yes {
+            bail!("{} already defines services", cfg.path.display());
+        }
+        return Ok(());
+    }
+    let import = compose::import(&path)?;
+    if import.services.is_empty() {
+        println!("{} has no services to import", path.display());
+        return Ok(());
+    }
 
+    let names: Vec<_> = import.services.iter().map(|s| s.name.as_str()).collect();
+    let file = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut question = format!("Import services {} from {file}", names.join(", "));
+    if !import.replaced.is_empty() {
+        question.push_str(&format!(
+            " (leaving out {}, which the dev container replaces)",
+            import.replaced.join(", ")
+        ));
+    }
+    if !assume_yes {
+        if !std::io::stdin().is_terminal() {
+            warn!("Found {file}; run `devenv init --compose` to import its services");
+            return Ok(());
+        }
+        if !confirm(&format!("{question}?"))? {
+            return Ok(());
+        }
+    }
+
+    cfg.devenv.services = import.services;
+    cfg.save()?;
+    println!("{question}: added to {}", cfg.path.display());
+    for warning in import.warnings {
+        warn!("Warning: {warning}");
+    }
     Ok(())
 }
 
+fn confirm(question: &str) -> Result<bool> {
+    print!("{question} [Y/n] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "" | "y" | "yes"
+    ))
+}
+
 async fn cmd_list() -> Result<()> {
     let docker = DockerClient::new()?;
     let items = docker.ps().await?;
     if items.is_empty() {
         info!("No running dev environments");
     } else {
-        for it in items {
-            info!("{}\t{}\t{}", it.name, it.image, it.status);
+        for line in format_ps(&items) {
+            info!("{line}");
         }
     }
     Ok(())
 }
 
+// One line per environment, followed by its services indented beneath it
+fn format_ps(items: &[PsItem]) -> Vec<String> {
+    let line = |it: &PsItem| format!("{}\t{}\t{}", it.name, it.image, it.status);
+    let (services, envs): (Vec<_>, Vec<_>) = items.iter().partition(|it| it.service.is_some());
+    let mut out = Vec::new();
+    let mut shown = vec![false; services.len()];
+    for env in envs {
+        out.push(line(env));
+        for (svc, shown) in services.iter().zip(&mut shown) {
+            if let Some((env_name, name)) = &svc.service
+                && env.name == format!("devenv-{env_name}")
+            {
+                out.push(format!("  {name}\t{}\t{}", svc.image, svc.status));
+                *shown = true;
+            }
+        }
+    }
+    // Services whose dev container is not running
+    out.extend(
+        services
+            .iter()
+            .zip(shown)
+            .filter(|(_, shown)| !shown)
+            .map(|(svc, _)| line(svc)),
+    );
+    out
+}
+
 async fn cmd_start(
     name: Option<&str>,
     open_cmd: Option<&str>,
@@ -140,37 +299,47 @@ async fn cmd_start(
     no_build: bool,
 ) -> Result<()> {
     let project_dir = resolve_env(name)?;
+    let report = validate(&project_dir)?;
+    if !report.is_ok() {
+        eprint!("{}", report.render());
+        bail!(
+            "{} is invalid; fix it before starting the environment",
+            report.path.display()
+        );
+    }
     let cfg = Config::open(&project_dir)?;
 
     // Check if the environment has already started
     let container_name = format!("devenv-{}", cfg.devenv.name);
     let docker = DockerClient::new()?;
+    let layout = Layout {
+        ports: cfg.devenv.ports.iter().map(|p| p.to_string()).collect(),
+        mounts: dev_mounts(&cfg)?,
+        resources: resource_limits(&cfg)?,
+    };
     let running = docker.is_container_running(&container_name).await?;
     if running {
-        info!("Environment '{}' is already running.", cfg.devenv.name);
-        return Ok(());
-    }
-
-    // Create/rebuild Dockerfile as necessary
-    let expected = Dockerfile::create(&cfg.devenv.image, &cfg.devenv.packages, OsFamily::Debian)?;
-    if Dockerfile::exists(&project_dir) {
-        let current = Dockerfile::open(&project_dir)?;
-        if current != expected {
+        if docker.layout_changed(&container_name, &layout).await? {
             warn!(
-                "Warning: Dockerfile is out of sync with devenv.toml. Use the `--rebuild` flag to regenerate."
+                "Warning: the ports, mounts or resources of '{container_name}' changed. Run `devenv stop` and start again to apply them."
             );
         }
-    } else {
-        expected.write(&project_dir)?;
-        info!("Rebuilt {} from devenv.toml", project_dir.display());
+        info!("Environment '{}' is already running.", cfg.devenv.name);
+        return Ok(());
     }
 
-    // Build image unless user asks us not to
-    let image_tag = format!("devenv-{}:latest", cfg.devenv.name);
+    // Create the Dockerfile if missing, warn if it has drifted
+    sync_dockerfile(&cfg, false)?;
+
+    // Build image unless user asks us not to, or an image for this exact content exists
+    let opts = build_options(&cfg, false, rebuild)?;
+    let image_tag = opts.tag.clone();
     if !no_build {
-        docker
-            .build_with_opts(&project_dir, &image_tag, false, rebuild)
-            .await?;
+        if rebuild || !docker.image_exists(&image_tag).await? {
+            docker.build_with_opts(&opts).await?;
+        } else {
+            info!("Image '{image_tag}' is up to date; skipping build");
+        }
     }
 
     // Determine SSH port if Zed remote is enabled
@@ -187,39 +356,69 @@ async fn cmd_start(
                 .and_then(|z| if z.enabled { Some(2222) } else { None })
         });
 
-    if docker.container_exists(&container_name).await? {
+    let env = container_env(&cfg)?;
+    let network = start_services(&docker, &cfg).await?;
+
+    let opts = RunOptions {
+        container_name: container_name.clone(),
+        image: image_tag.clone(),
+        project_dir: project_dir.clone(),
+        ssh_port,
+        network: network.clone(),
+        env: env.clone(),
+        layout,
+    };
+    // The layout is fixed at creation, so changing it needs a new container
+    if docker.dev_container_exists(&container_name).await?
+        && docker.layout_changed(&container_name, &opts.layout).await?
+    {
+        warn!(
+            "Warning: ports, mounts or resources changed; recreating '{container_name}'. Changes made inside the container outside /workspace and volumes are discarded."
+        );
+        docker.remove_container(&container_name, false).await?;
+    }
+    if docker.dev_container_exists(&container_name).await? {
+        if let Some(current) = docker.container_image(&container_name).await?
+            && current != image_tag
+        {
+            warn!(
+                "Warning: container '{container_name}' uses image '{current}', not '{image_tag}'. Run `devenv remove` and start again to use the new image."
+            );
+        }
+        if let Some(network) = &network {
+            docker.connect_network(network, &container_name).await?;
+        }
         docker.start(&container_name).await?;
     } else {
+        docker.run_detached(&opts).await?;
+    }
+
+    // Provisioning commands may need the services, so wait for them to be ready
+    for svc in &cfg.devenv.services {
+        let name = service_container(&cfg.devenv.name, &svc.name);
         docker
-            .run_detached(&container_name, &image_tag, &project_dir, ssh_port)
-            .await?;
+            .wait_healthy(&name)
+            .await
+            .with_context(|| format!("Waiting for service '{}'", svc.name))?;
     }
 
     // Run provisioning commands if any
     if !cfg.devenv.commands.is_empty() {
         info!("Running provisioning commands...");
         // Choose user to run provisioning
-        let non_root_user = cfg
-            .devenv
-            .user_name
-            .clone()
-            .or_else(|| {
-                cfg.devenv
-                    .zed_remote
-                    .as_ref()
-                    .and_then(|z| z.ssh_user.clone())
-            })
-            .filter(|u| u != "root");
+        let non_root_user = cfg.devenv.non_root_user();
         for cmd in &cfg.devenv.commands {
             info!("$ {cmd}");
             if cfg.devenv.provision_as_non_root {
-                if let Some(user) = non_root_user.as_deref() {
-                    docker.exec_shell_as(&container_name, user, cmd).await?;
+                if let Some(user) = non_root_user {
+                    docker
+                        .exec_shell_as(&container_name, user, cmd, &env)
+                        .await?;
                 } else {
-                    docker.exec_shell(&container_name, cmd).await?;
+                    docker.exec_shell(&container_name, cmd, &env).await?;
                 }
             } else {
-                docker.exec_shell(&container_name, cmd).await?;
+                docker.exec_shell(&container_name, cmd, &env).await?;
             }
         }
     }
@@ -229,7 +428,7 @@ async fn cmd_start(
         && z.enabled
     {
         let start_sshd = "mkdir -p /run/sshd && (service ssh start || (which /usr/sbin/sshd && /usr/sbin/sshd) || (which sshd && sshd) || true)";
-        let _ = docker.exec_shell(&container_name, start_sshd).await;
+        let _ = docker.exec_shell(&container_name, start_sshd, &env).await;
     }
 
     // Ensure project-managed keys exist and add to authorized_keys; update .gitignore if present
@@ -260,7 +459,7 @@ async fn cmd_start(
             user = user,
             key = key.trim().replace("'", "'\\''"),
         );
-        let _ = docker.exec_shell(&container_name, &script).await;
+        let _ = docker.exec_shell(&container_name, &script, &env).await;
     }
 
     info!("Environment '{}' started.", cfg.devenv.name);
@@ -270,7 +469,7 @@ async fn cmd_start(
         let _ = Command::new(cmd).arg(&target).spawn();
     }
     if attach {
-        return docker.exec_interactive_shell(&container_name).await;
+        return docker.exec_interactive_shell(&container_name, &env).await;
     }
     Ok(())
 }
@@ -285,30 +484,40 @@ async fn cmd_stop(name: Option<&str>) -> Result<()> {
     };
     let container_name = format!("devenv-{}", effective_name);
     let docker = DockerClient::new()?;
-    if !docker.container_exists(&container_name).await? {
+    if !docker.dev_container_exists(&container_name).await? {
         info!("Environment '{}' is not created.", effective_name);
-        return Ok(());
-    }
-    if docker.is_container_running(&container_name).await? {
+    } else if docker.is_container_running(&container_name).await? {
         docker.stop(&container_name).await?;
         info!("Environment '{}' stopped.", effective_name);
     } else {
         info!("Environment '{}' is not running.", effective_name);
     }
+    for svc in docker.service_containers(&effective_name).await? {
+        if docker.is_container_running(&svc.name).await? {
+            docker.stop(&svc.name).await?;
+            info!("Stopped '{}'", svc.name);
+        }
+    }
     Ok(())
 }
 
 async fn cmd_attach(name: Option<&str>) -> Result<()> {
-    let effective_name = if let Some(n) = name {
-        n.to_string()
+    let (effective_name, env) = if let Some(n) = name {
+        // The environment may not be registered; attach without its env then
+        let env = match resolve_env(name).and_then(|path| Config::open(&path)) {
+            Ok(cfg) => container_env(&cfg)?,
+            Err(_) => Vec::new(),
+        };
+        (n.to_string(), env)
     } else {
         let path = resolve_env(None)?;
         let cfg = Config::open(&path)?;
-        cfg.devenv.name
+        let env = container_env(&cfg)?;
+        (cfg.devenv.name, env)
     };
     let container_name = format!("devenv-{}", effective_name);
     let docker = DockerClient::new()?;
-    if !docker.container_exists(&container_name).await? {
+    if !docker.dev_container_exists(&container_name).await? {
         anyhow::bail!("Environment '{}' does not exist.", effective_name);
     }
     if !docker.is_container_running(&container_name).await? {
@@ -324,7 +533,87 @@ async fn cmd_attach(name: Option<&str>) -> Result<()> {
         );
     }
     info!("Attaching to '{container_name}'... (exit to detach)");
-    docker.exec_interactive_shell(&container_name).await
+    docker.exec_interactive_shell(&container_name, &env).await
+}
+
+async fn cmd_ports(name: Option<&str>) -> Result<()> {
+    let effective_name = if let Some(n) = name {
+        n.to_string()
+    } else {
+        let path = resolve_env(None)?;
+        let cfg = Config::open(&path)?;
+        cfg.devenv.name
+    };
+    let container_name = format!("devenv-{}", effective_name);
+    let docker = DockerClient::new()?;
+    if !docker.dev_container_exists(&container_name).await? {
+        bail!("Environment '{}' does not exist.", effective_name);
+    }
+    if !docker.is_container_running(&container_name).await? {
+        bail!("Environment '{}' is not running.", effective_name);
+    }
+    let ports = docker.published_ports(&container_name).await?;
+    if ports.is_empty() {
+        println!("No ports are published.");
+    }
+    for (port, addr) in ports {
+        println!("{port} -> {addr}");
+    }
+    Ok(())
+}
+
+async fn cmd_stats(name: Option<&str>) -> Result<()> {
+    let effective_name = if let Some(n) = name {
+        n.to_string()
+    } else {
+        let path = resolve_env(None)?;
+        let cfg = Config::open(&path)?;
+        cfg.devenv.name
+    };
+    let container_name = format!("devenv-{}", effective_name);
+    let docker = DockerClient::new()?;
+    if !docker.dev_container_exists(&container_name).await?
+        || !docker.is_container_running(&container_name).await?
+    {
+        bail!("Environment '{}' is not running.", effective_name);
+    }
+    let mut rows = vec![(container_name.clone(), docker.usage(&container_name).await?)];
+    for svc in docker.service_containers(&effective_name).await? {
+        if docker.is_container_running(&svc.name).await? {
+            let usage = docker.usage(&svc.name).await?;
+            rows.push((svc.name, usage));
+        }
+    }
+    for line in format_stats(&rows) {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+// Usage next to the limit, when the container has one
+fn format_stats(rows: &[(String, Usage)]) -> Vec<String> {
+    let mut out = vec!["NAME\tCPU\tMEMORY\tPIDS".to_string()];
+    for (name, u) in rows {
+        let cpu = match u.cpus {
+            Some(cpus) => format!("{:.1}% / {:.0}%", u.cpu_percent, cpus * 100.0),
+            None => format!("{:.1}%", u.cpu_percent),
+        };
+        let memory = match u.memory_limit {
+            Some(limit) => format!(
+                "{} / {} ({:.0}%)",
+                format_size(u.memory),
+                format_size(limit),
+                u.memory as f64 / limit as f64 * 100.0
+            ),
+            None => format_size(u.memory),
+        };
+        let pids = match u.pids_limit {
+            Some(limit) => format!("{} / {limit}", u.pids),
+            None => u.pids.to_string(),
+        };
+        out.push(format!("{name}\t{cpu}\t{memory}\t{pids}"));
+    }
+    out
 }
 
 async fn cmd_remove(name: Option<&str>) -> Result<()> {
@@ -337,7 +626,7 @@ async fn cmd_remove(name: Option<&str>) -> Result<()> {
     };
     let container_name = format!("devenv-{}", effective_name);
     let docker = DockerClient::new()?;
-    if docker.container_exists(&container_name).await? {
+    if docker.dev_container_exists(&container_name).await? {
         if docker.is_container_running(&container_name).await? {
             docker.stop(&container_name).await?;
             info!("Stopped '{container_name}'");
@@ -347,6 +636,14 @@ async fn cmd_remove(name: Option<&str>) -> Result<()> {
     } else {
         info!("No container named '{container_name}' found.");
     }
+    for svc in docker.service_containers(&effective_name).await? {
+        docker.remove_container(&svc.name, true).await?;
+        info!("Removed container '{}'", svc.name);
+    }
+    let network = network_name(&effective_name);
+    if docker.remove_network(&network).await? {
+        info!("Removed network '{network}'");
+    }
 
     match registry::unregister_env(&effective_name) {
         Ok(true) => info!("Unregistered environment '{}'", effective_name),
@@ -374,7 +671,7 @@ async fn cmd_restart(
     let container_name = format!("devenv-{}", effective_name);
     let docker = DockerClient::new()?;
     match (
-        docker.container_exists(&container_name).await?,
+        docker.dev_container_exists(&container_name).await?,
         docker.is_container_running(&container_name).await?,
     ) {
         (true, true) => {
@@ -401,29 +698,395 @@ async fn cmd_build(name: Option<&str>, rebuild: bool, pull: bool) -> Result<()>
     let path = resolve_env(name)?;
     let cfg = Config::open(&path)?;
 
-    let expected = Dockerfile::create(&cfg.devenv.image, &cfg.devenv.packages, OsFamily::Debian)?;
-    if rebuild || !Dockerfile::exists(&path) {
-        expected.write(&path)?;
-        info!("Dockerfile written from devenv.toml at {}", path.display());
-    } else {
-        let current = Dockerfile::open(&path)?;
-        if current != expected {
-            warn!("Warning: Dockerfile differs from generated; consider --rebuild.");
-        }
-    }
-    let image_tag = format!("devenv-{}:latest", cfg.devenv.name);
+    sync_dockerfile(&cfg, rebuild)?;
+    let opts = build_options(&cfg, pull, false)?;
     info!(
         "Building image '{}' (FROM {})...",
-        image_tag, cfg.devenv.image
+        opts.tag, cfg.devenv.image
     );
     let docker = DockerClient::new()?;
-    docker
-        .build_with_opts(&path, &image_tag, pull, false)
-        .await?;
-    info!("Image built: {image_tag}");
+    docker.build_with_opts(&opts).await?;
+    info!("Image built: {}", opts.tag);
+    Ok(())
+}
+
+fn cmd_detect(path: Option<PathBuf>, depth: usize, explain: bool, json: bool) -> Result<()> {
+    let project_dir = match path {
+        Some(path) => path,
+        None => std::env::current_dir()?,
+    };
+    if !project_dir.is_dir() {
+        bail!("Path is not a directory: {}", project_dir.display());
+    }
+
+    let detection = Detector::load(depth)?.detect(&project_dir);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&detection)?);
+    } else {
+        print!("{}", detection.render(explain));
+    }
+    Ok(())
+}
+
+fn cmd_export_devcontainer(name: Option<&str>, force: bool) -> Result<()> {
+    let path = resolve_env(name)?;
+    let cfg = Config::open(&path)?;
+    // The devcontainer builds the generated Dockerfile, so make sure it exists
+    sync_dockerfile(&cfg, false)?;
+
+    let (path, warnings) = devcontainer::export(&cfg, force)?;
+    for warning in warnings {
+        warn!("Warning: {warning}");
+    }
+    info!("Wrote {}", path.display());
+    Ok(())
+}
+
+fn cmd_config_validate(name: Option<&str>) -> Result<()> {
+    let path = resolve_env(name)?;
+    let report = validate(&path)?;
+    if !report.is_ok() {
+        eprint!("{}", report.render());
+        bail!("{} is invalid", report.path.display());
+    }
+    println!("{} is valid", report.path.display());
+    Ok(())
+}
+
+fn cmd_config_show(name: Option<&str>, resolved: bool) -> Result<()> {
+    let path = config::make_path(resolve_env(name)?);
+    if !resolved {
+        let source =
+            fs::read_to_string(&path).with_context(|| format!("Reading {}", path.display()))?;
+        print!("{source}");
+        return Ok(());
+    }
+    let layers = layers::load(&path)?;
+    let cfg = validate::parse(&layers)?;
+    print!("{}", layers.annotate(&toml::to_string_pretty(&cfg)?));
+    Ok(())
+}
+
+fn cmd_dockerfile_diff(name: Option<&str>, check: bool) -> Result<()> {
+    let path = resolve_env(name)?;
+    let cfg = Config::open(&path)?;
+    let dockerfile_path = cfg.dockerfile_path();
+    let expected = Dockerfile::create(&cfg.devenv, &path)?;
+    let current = if Dockerfile::exists(&dockerfile_path) {
+        Dockerfile::open(&dockerfile_path)?
+    } else {
+        Dockerfile::default()
+    };
+
+    match current.diff(&expected, &dockerfile_label(&cfg)) {
+        Some(diff) => {
+            print!("{diff}");
+            if check {
+                bail!(
+                    "{} is out of sync with devenv.toml",
+                    dockerfile_path.display()
+                );
+            }
+        }
+        None => println!("{} is in sync with devenv.toml", dockerfile_path.display()),
+    }
+    Ok(())
+}
+
+// Write the generated Dockerfile when it is missing or `regenerate` is set,
+// otherwise warn (with a diff in verbose mode) if it has drifted from devenv.toml
+fn sync_dockerfile(cfg: &Config, regenerate: bool) -> Result<()> {
+    let expected = Dockerfile::create(&cfg.devenv, cfg.project_dir())?;
+    let dockerfile_path = cfg.dockerfile_path();
+    if regenerate || !Dockerfile::exists(&dockerfile_path) {
+        expected.write(&dockerfile_path)?;
+        info!(
+            "Dockerfile written from devenv.toml at {}",
+            dockerfile_path.display()
+        );
+    } else if let Some(diff) =
+        Dockerfile::open(&dockerfile_path)?.diff(&expected, &dockerfile_label(cfg))
+    {
+        warn!(
+            "Warning: {} is out of sync with devenv.toml. Use the `--rebuild` flag to regenerate, or `devenv dockerfile diff` to see the changes.",
+            dockerfile_path.display()
+        );
+        debug!("{diff}");
+    }
+    Ok(())
+}
+
+// Dockerfile path relative to the project, for diff headers
+fn dockerfile_label(cfg: &Config) -> String {
+    let path = cfg.dockerfile_path();
+    path.strip_prefix(cfg.project_dir())
+        .unwrap_or(&path)
+        .display()
+        .to_string()
+}
+
+async fn cmd_images(name: Option<&str>) -> Result<()> {
+    let path = resolve_env(name)?;
+    let cfg = Config::open(&path)?;
+    let current = if Dockerfile::exists(cfg.dockerfile_path()) {
+        Some(build_options(&cfg, false, false)?.tag)
+    } else {
+        None
+    };
+
+    let docker = DockerClient::new()?;
+    let images = docker.images(&image_repo(&cfg.devenv.name)).await?;
+    if images.is_empty() {
+        println!("No images built for '{}'", cfg.devenv.name);
+    }
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    for image in images {
+        let marker = if current.as_deref() == Some(image.tag.as_str()) {
+            "*"
+        } else {
+            " "
+        };
+        println!(
+            "{marker} {}\t{}\t{:.1} MB",
+            image.tag,
+            format_age(now - image.created),
+            image.size as f64 / 1_000_000.0
+        );
+    }
     Ok(())
 }
 
+fn build_options(cfg: &Config, pull: bool, no_cache: bool) -> Result<BuildOptions> {
+    let build_args = cfg
+        .devenv
+        .build_args
+        .iter()
+        .map(|(k, v)| Ok((k.clone(), interpolate(v)?)))
+        .collect::<Result<_>>()
+        .context("Interpolating build_args")?;
+    let secrets = cfg
+        .devenv
+        .build_secrets
+        .iter()
+        .map(|(id, path)| Ok((id.clone(), expand_path(path, cfg.project_dir())?)))
+        .collect::<Result<_>>()
+        .context("Resolving build_secrets")?;
+    let mut opts = BuildOptions {
+        context_dir: cfg.context_dir(),
+        dockerfile: cfg.dockerfile_path(),
+        tag: String::new(),
+        target: cfg.devenv.target.clone(),
+        build_args,
+        secrets,
+        pull,
+        no_cache,
+    };
+    // Tag images by content so unchanged environments can skip the build
+    let digest = image_digest(&opts)?;
+    opts.tag = format!("{}:{}", image_repo(&cfg.devenv.name), &digest[..TAG_LEN]);
+    Ok(opts)
+}
+
+fn image_repo(env_name: &str) -> String {
+    format!("devenv-{env_name}")
+}
+
+fn network_name(env_name: &str) -> String {
+    format!("devenv-{env_name}")
+}
+
+fn service_container(env_name: &str, service: &str) -> String {
+    format!("devenv-{env_name}-{service}")
+}
+
+/// Start the configured services on the environment's network, removing
+/// containers of services no longer in the config. Returns the network name
+/// when there are services.
+async fn start_services(docker: &DockerClient, cfg: &Config) -> Result<Option<String>> {
+    let env_name = &cfg.devenv.name;
+    for stale in docker.service_containers(env_name).await? {
+        if let Some((_, svc)) = &stale.service
+            && !cfg.devenv.services.iter().any(|s| &s.name == svc)
+        {
+            docker.remove_container(&stale.name, true).await?;
+            info!("Removed service '{svc}' (no longer in devenv.toml)");
+        }
+    }
+    if cfg.devenv.services.is_empty() {
+        return Ok(None);
+    }
+
+    let network = network_name(env_name);
+    docker.ensure_network(&network).await?;
+    for (i, svc) in cfg.devenv.services.iter().enumerate() {
+        for dep in &svc.depends_on {
+            if !cfg.devenv.services[..i].iter().any(|s| &s.name == dep) {
+                bail!(
+                    "Service '{}' depends on '{dep}', which must be listed before it",
+                    svc.name
+                );
+            }
+            docker
+                .wait_healthy(&service_container(env_name, dep))
+                .await
+                .with_context(|| format!("Waiting for service '{dep}'"))?;
+        }
+        let opts = service_options(cfg, svc, &network)
+            .with_context(|| format!("Service '{}'", svc.name))?;
+        docker.start_service(&opts).await?;
+    }
+    Ok(Some(network))
+}
+
+/// `KEY=VALUE` pairs for the dev container: the `env_file` first, then
+/// `[devenv.env]`, both interpolated from the host environment
+fn container_env(cfg: &Config) -> Result<Vec<String>> {
+    let mut env = BTreeMap::new();
+    if let Some(file) = &cfg.devenv.env_file {
+        let path = expand_path(file, cfg.project_dir())?;
+        if !path.exists() {
+            bail!("env_file '{}' does not exist", path.display());
+        }
+        env.extend(dotenv::load(&path)?);
+    }
+    for (key, value) in &cfg.devenv.env {
+        let value = interpolate(value).with_context(|| format!("Interpolating env.{key}"))?;
+        env.insert(key.clone(), value);
+    }
+    Ok(env.into_iter().map(|(k, v)| format!("{k}={v}")).collect())
+}
+
+fn resource_limits(cfg: &Config) -> Result<ResourceLimits> {
+    let Some(res) = &cfg.devenv.resources else {
+        return Ok(ResourceLimits::default());
+    };
+    let size = |s: &Option<String>, key: &str| {
+        s.as_deref()
+            .map(parse_size)
+            .transpose()
+            .with_context(|| format!("resources.{key}"))
+    };
+    Ok(ResourceLimits {
+        nano_cpus: res.cpus.map(|c| (c * 1e9) as i64),
+        memory: size(&res.memory, "memory")?,
+        memory_swap: match res.memory_swap.as_deref() {
+            Some("-1") => Some(-1),
+            _ => size(&res.memory_swap, "memory_swap")?,
+        },
+        pids_limit: res.pids_limit,
+        shm_size: size(&res.shm_size, "shm_size")?,
+        ulimits: res
+            .ulimits
+            .iter()
+            .map(|(name, limit)| {
+                let (soft, hard) = limit.soft_hard();
+                (name.clone(), soft, hard)
+            })
+            .collect(),
+    })
+}
+
+/// Extra dev container mounts with bind sources resolved on the host and
+/// volume names scoped to the environment
+fn dev_mounts(cfg: &Config) -> Result<Vec<MountOptions>> {
+    cfg.devenv
+        .mounts
+        .iter()
+        .map(|m| {
+            let source = match m.kind {
+                MountKind::Bind => {
+                    let source = m
+                        .source
+                        .as_deref()
+                        .with_context(|| format!("Bind mount '{}' needs a source", m.target))?;
+                    let path = expand_path(source, cfg.project_dir())?;
+                    if !path.exists() {
+                        bail!(
+                            "Mount source '{}' for '{}' does not exist",
+                            path.display(),
+                            m.target
+                        );
+                    }
+                    Some(path.to_string_lossy().to_string())
+                }
+                MountKind::Volume => m
+                    .source
+                    .as_ref()
+                    .map(|name| format!("devenv-{}-{name}", cfg.devenv.name)),
+                MountKind::Tmpfs => None,
+            };
+            Ok(MountOptions {
+                kind: m.kind,
+                source,
+                target: m.target.clone(),
+                read_only: m.read_only,
+            })
+        })
+        .collect()
+}
+
+fn service_options(cfg: &Config, svc: &Service, network: &str) -> Result<ServiceOptions> {
+    let env_name = &cfg.devenv.name;
+    let env = svc
+        .env
+        .iter()
+        .map(|(k, v)| Ok((k.clone(), interpolate(v)?)))
+        .collect::<Result<_>>()
+        .context("Interpolating env")?;
+    // Host paths are bind-mounted; named volumes are scoped to the environment
+    let volumes = svc
+        .volumes
+        .iter()
+        .map(|spec| {
+            let (source, target) = spec
+                .split_once(':')
+                .with_context(|| format!("Volume '{spec}' must be SOURCE:TARGET"))?;
+            let source = if source.starts_with(['.', '/', '~']) {
+                expand_path(source, cfg.project_dir())?
+                    .to_string_lossy()
+                    .to_string()
+            } else {
+                format!("devenv-{env_name}-{source}")
+            };
+            Ok(format!("{source}:{target}"))
+        })
+        .collect::<Result<_>>()?;
+    let duration = |d: &Option<String>| d.as_deref().map(parse_duration).transpose();
+    let healthcheck = match &svc.healthcheck {
+        Some(hc) => Some(Healthcheck {
+            test: hc.test.clone(),
+            interval: duration(&hc.interval).context("healthcheck.interval")?,
+            timeout: duration(&hc.timeout).context("healthcheck.timeout")?,
+            start_period: duration(&hc.start_period).context("healthcheck.start_period")?,
+            retries: hc.retries,
+        }),
+        None => None,
+    };
+    Ok(ServiceOptions {
+        env_name: env_name.clone(),
+        service: svc.name.clone(),
+        container_name: service_container(env_name, &svc.name),
+        network: network.to_string(),
+        image: svc.image.clone(),
+        env,
+        ports: svc.ports.clone(),
+        volumes,
+        healthcheck,
+    })
+}
+
+fn format_age(secs: i64) -> String {
+    match secs.max(0) {
+        s if s < 60 => "just now".to_string(),
+        s if s < 3600 => format!("{} minutes ago", s / 60),
+        s if s < 86400 => format!("{} hours ago", s / 3600),
+        s => format!("{} days ago", s / 86400),
+    }
+}
+
 // Resolve environment by:
 // 1. User-provided project name via Registry, or
 // 2. By looking for `devenv.toml` in CWD
@@ -470,13 +1133,18 @@ fn update_project_gitignore(project_dir: &std::path::Path) -> Result<()> {
     let gi = project_dir.join(".gitignore");
     if gi.exists() {
         let mut content = fs::read_to_string(&gi).unwrap_or_default();
-        let line = "/.devenv";
-        if !content.lines().any(|l| l.trim() == line) {
-            if !content.ends_with('\n') {
+        let original = content.clone();
+        let local = format!("/{}", config::LOCAL_FILENAME);
+        for line in ["/.devenv", local.as_str()] {
+            if !content.lines().any(|l| l.trim() == line) {
+                if !content.ends_with('\n') {
+                    content.push('\n');
+                }
+                content.push_str(line);
                 content.push('\n');
             }
-            content.push_str(line);
-            content.push('\n');
+        }
+        if content != original {
             fs::write(gi, content)?;
         }
     }
@@ -496,7 +1164,7 @@ mod tests {
 
         update_project_gitignore(td.path()).unwrap();
         let content = std::fs::read_to_string(&gi).unwrap();
-        assert!(content.lines().any(|l| l.trim() == "/.devenv"));
+        assert_eq!(content, "target/\n/.devenv\n/devenv.local.toml\n");
 
         // Calling again should not duplicate the entry
         update_project_gitignore(td.path()).unwrap();
@@ -504,6 +1172,66 @@ mod tests {
         assert_eq!(content, content2);
     }
 
+    #[test]
+    fn formats_usage_against_limits() {
+        let limited = Usage {
+            cpu_percent: 143.25,
+            cpus: Some(2.0),
+            memory: 1 << 30,
+            memory_limit: Some(4 << 30),
+            pids: 87,
+            pids_limit: Some(512),
+        };
+        let unlimited = Usage {
+            cpu_percent: 0.5,
+            memory: 45 << 20,
+            pids: 12,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_stats(&[
+                ("devenv-app".into(), limited),
+                ("devenv-app-db".into(), unlimited)
+            ]),
+            vec![
+                "NAME\tCPU\tMEMORY\tPIDS",
+                "devenv-app\t143.2% / 200%\t1.0GiB / 4.0GiB (25%)\t87 / 512",
+                "devenv-app-db\t0.5%\t45.0MiB\t12",
+            ]
+        );
+    }
+
+    #[test]
+    fn groups_services_under_environments() {
+        let item = |name: &str, service: Option<(&str, &str)>| PsItem {
+            name: name.into(),
+            image: "img".into(),
+            status: "Up".into(),
+            service: service.map(|(e, s)| (e.into(), s.into())),
+        };
+        let items = [
+            item("devenv-api-db", Some(("api", "db"))),
+            item("devenv-web-redis", Some(("web", "redis"))),
+            item("devenv-api", None),
+        ];
+        assert_eq!(
+            format_ps(&items),
+            vec![
+                "devenv-api\timg\tUp",
+                "  db\timg\tUp",
+                "devenv-web-redis\timg\tUp",
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_image_age() {
+        assert_eq!(format_age(5), "just now");
+        assert_eq!(format_age(125), "2 minutes ago");
+        assert_eq!(format_age(7200), "2 hours ago");
+        assert_eq!(format_age(3 * 86400 + 5), "3 days ago");
+    }
+
     #[test]
     fn no_gitignore_is_ok() {
         let td = TempDir::new().unwrap();
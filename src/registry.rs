@@ -10,14 +10,19 @@ struct Registry {
     envs: BTreeMap<String, PathBuf>,
 }
 
-fn registry_path() -> PathBuf {
+/// Directory holding devenv's global configuration
+pub fn devenv_config_dir() -> PathBuf {
     // Prefer XDG_CONFIG_HOME when set (useful for tests and Linux setups),
     // otherwise fall back to platform default via dirs::config_dir.
     let base = std::env::var_os("XDG_CONFIG_HOME")
         .map(PathBuf::from)
         .or_else(config_dir)
         .unwrap_or_else(|| PathBuf::from("."));
-    base.join("devenv").join("registry.json")
+    base.join("devenv")
+}
+
+fn registry_path() -> PathBuf {
+    devenv_config_dir().join("registry.json")
 }
 
 fn load_registry() -> Result<Registry> {
@@ -0,0 +1,40 @@
+//! Small conversions shared by the importers and the Docker context code
+
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use serde_json::Value;
+
+/// The string at `key`, failing for any other JSON value
+pub fn as_string(key: &str, value: &Value) -> Result<String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        _ => bail!("{key} must be a string"),
+    }
+}
+
+/// A JSON scalar as text: strings unquoted, numbers and booleans as written
+pub fn scalar_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Quote `arg` for a POSIX shell, leaving plain words as they are
+pub fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@%+,".contains(c))
+    {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// `path` with `/` separators, as Docker and devcontainer.json expect
+pub fn slash_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}